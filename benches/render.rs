@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, BatchSize, criterion_main, Criterion};
+use rust_web_markdown::{render_markdown_incremental, render_to_html_string, table_of_contents, BlockCache,
+                        MarkdownOptions, StringContext};
+
+/// a long document, with every kind of block
+fn big_document() -> String {
+    let section = "# Section\n\nSome *text* with `code` and a [link](https://a.b).\n\n\
+                   - a\n- b\n  1. c\n\n> quote\n\n```rust\nfn main(){}\n```\n\n\
+                   | a | b |\n|---|---|\n| 1 | 2 |\n\n";
+    section.repeat(2_000)
+}
+
+/// the explicit heading ids are collected with a second parse of the document
+fn big_table_of_contents(c: &mut Criterion) {
+    let source = big_document() + "# Last {#last}\n";
+    c.bench_function("table of contents of a big document", |b| {
+        b.iter(|| table_of_contents(black_box(&source), None, 0))
+    });
+}
+
+/// renders the whole document, without reusing anything
+fn render_big_document(c: &mut Criterion) {
+    let source = big_document();
+    let props = MarkdownOptions::new().build();
+    c.bench_function("render a big document", |b| {
+        b.iter(|| render_to_html_string(black_box(&source), props))
+    });
+}
+
+/// renders the document after a change in its last block,
+/// with the blocks of the document before the change
+fn render_edited_end(c: &mut Criterion) {
+    let source = big_document();
+    let edited = source.clone() + "\nan edit";
+    let props = MarkdownOptions::new().build();
+    c.bench_function("render an edit at the end of a big document", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut cache = BlockCache::new();
+                render_markdown_incremental(StringContext::new(props), &source, &mut cache);
+                cache
+            },
+            |cache| render_markdown_incremental(StringContext::new(props), black_box(&edited), cache),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, big_table_of_contents, render_big_document, render_edited_end);
+criterion_main!(benches);
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use syntect::highlighting::Theme;
+
+use crate::render::find_theme;
+use crate::HtmlErrorKind;
+
+/// the number of highlighted code blocks kept in a [`RenderCache`]
+const HIGHLIGHT_CACHE_SIZE: usize = 64;
+
+/// what the html of a highlighted code block depends on
+#[derive(PartialEq)]
+pub(crate) struct CodeKey {
+    /// the name of the theme, `None` for the default theme
+    pub theme: Option<String>,
+    /// the info string of the code block, like `rust {1,3}`
+    pub info: String,
+    pub line_numbers: bool,
+    pub source: String,
+}
+
+/// Data that is kept between renders:
+/// the syntax highlighting theme, the html of the last highlighted code blocks,
+/// and the math options.
+///
+/// Without a cache, the theme is looked up for every code block,
+/// every code block is highlighted again even if it didn't change,
+/// and the math options are built for every formula.
+/// To keep them, create one cache and give it to every render with [`crate::MarkdownProps::cache`].
+pub struct RenderCache {
+    /// the name of the cached theme, `None` for the default theme
+    theme_name: Option<String>,
+    /// the cached theme, `None` if it doesn't exist
+    theme: Option<&'static Theme>,
+    /// the html of the last highlighted code blocks,
+    /// from the most recently used to the least recently used
+    highlighted: RefCell<VecDeque<(CodeKey, Option<String>)>>,
+    #[cfg(feature="maths")]
+    inline_math_opts: katex::Opts,
+    #[cfg(feature="maths")]
+    display_math_opts: katex::Opts,
+}
+
+impl RenderCache {
+    /// creates a cache for renders using the syntax highlighting theme `theme`
+    pub fn new(theme: Option<&str>) -> Self {
+        Self {
+            theme_name: theme.map(|x| x.to_string()),
+            theme: find_theme(theme).ok(),
+            highlighted: RefCell::new(VecDeque::new()),
+            #[cfg(feature="maths")]
+            inline_math_opts: crate::render::math_opts(false, None, false, Default::default()),
+            #[cfg(feature="maths")]
+            display_math_opts: crate::render::math_opts(true, None, false, Default::default()),
+        }
+    }
+
+    /// returns the theme called `name`.
+    /// It is only looked up if it is not the cached one.
+    pub(crate) fn theme(&self, name: Option<&str>) -> Result<&Theme, HtmlErrorKind> {
+        match self.theme {
+            Some(theme) if self.theme_name.as_deref() == name => Ok(theme),
+            _ => find_theme(name)
+        }
+    }
+
+    /// returns the html of the code block `key`,
+    /// highlighted with `highlight` if it is not in the cache.
+    /// When the cache is full, the least recently used code block is removed
+    pub(crate) fn highlighted_code(&self, key: CodeKey, highlight: impl FnOnce() -> Option<String>)
+        -> Option<String> {
+        let mut highlighted = self.highlighted.borrow_mut();
+        if let Some(i) = highlighted.iter().position(|(k, _)| k == &key) {
+            let entry = highlighted.remove(i)?;
+            let html = entry.1.clone();
+            highlighted.push_front(entry);
+            return html
+        }
+        let html = highlight();
+        if highlighted.len() == HIGHLIGHT_CACHE_SIZE {
+            highlighted.pop_back();
+        }
+        highlighted.push_front((key, html.clone()));
+        html
+    }
+
+    /// returns the options to render inline or display maths
+    #[cfg(feature="maths")]
+    pub(crate) fn math_opts(&self, display: bool) -> &katex::Opts {
+        if display {
+            &self.display_math_opts
+        }
+        else {
+            &self.inline_math_opts
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(source: &str) -> CodeKey {
+        CodeKey {
+            theme: None,
+            info: "rust".to_string(),
+            line_numbers: false,
+            source: source.to_string(),
+        }
+    }
+
+    #[test]
+    fn highlighted_once(){
+        let cache = RenderCache::new(None);
+        let mut calls = 0;
+        for _ in 0..3 {
+            let html = cache.highlighted_code(key("a"), || {calls += 1; Some("<b>a</b>".into())});
+            assert_eq!(html.as_deref(), Some("<b>a</b>"));
+        }
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn least_recently_used(){
+        let cache = RenderCache::new(None);
+        for i in 0..HIGHLIGHT_CACHE_SIZE {
+            cache.highlighted_code(key(&i.to_string()), || None);
+        }
+        // `0` is used again, so `1` is the least recently used
+        cache.highlighted_code(key("0"), || panic!("0 is cached"));
+        cache.highlighted_code(key("new"), || None);
+        cache.highlighted_code(key("0"), || panic!("0 is cached"));
+        let mut evicted = false;
+        cache.highlighted_code(key("1"), || {evicted = true; None});
+        assert!(evicted);
+    }
+}
@@ -1,8 +1,11 @@
 use std::str::FromStr;
 use core::iter::Peekable;
+use core::ops::Range;
 
 use std::collections::BTreeMap;
 
+use crate::utils::{decode_entities, tokenize_html, HtmlToken};
+
 #[derive(Debug, PartialEq)]
 /// a custom non-native html element
 /// called inside markdown
@@ -41,7 +44,12 @@ fn parse_attribute_value(stream: &mut Peekable<std::str::Chars>)
     }
     stream.next();
 
-    Ok(attribute)
+    Ok(decode_entities(&attribute))
+}
+
+/// wether `c` can be inside the name of an attribute, like `data-user`
+fn is_attribute_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')
 }
 
 fn parse_attribute_name(stream: &mut Peekable<std::str::Chars>) 
@@ -52,15 +60,20 @@ fn parse_attribute_name(stream: &mut Peekable<std::str::Chars>)
         stream.next();
     }
 
-    loop {
-        match stream.peek() {
-            None => return Err("expected equal sign after attribute name".into()),
-            Some(&'=') => break,
-            _ => name.push(stream.next().unwrap()),
-        }
+    while let Some(&c) = stream.peek().filter(|c| is_attribute_name_char(**c)) {
+        name.push(c);
+        stream.next();
+    }
+
+    while stream.peek() == Some(&' ') {
+        stream.next();
     }
 
-    Ok(name)
+    match stream.peek() {
+        _ if name.is_empty() => Err("expected attribute name".into()),
+        Some(&'=') => Ok(name),
+        _ => Err(format!("expected equal sign after attribute name `{name}`")),
+    }
 }
 
 fn parse_attribute(stream: &mut Peekable<std::str::Chars>) -> 
@@ -141,6 +154,39 @@ impl FromStr for CustomHtmlTag {
     }
 }
 
+/// parses `raw_html` as a sequence of components without children,
+/// like `<X/><Y a="b"/>`, separated by whitespace.
+/// In a sequence of several tags, the start tags are components without children,
+/// like `<X><X>`, since they can't be closed after the other tags.
+/// Returns the components with their position in `raw_html`,
+/// or None if there is anything else, or no component at all.
+pub fn parse_component_sequence(raw_html: &str) -> Option<Vec<(ComponentCall, Range<usize>)>> {
+    let mut calls = Vec::new();
+    let mut has_start = false;
+    for token in tokenize_html(raw_html) {
+        match token {
+            HtmlToken::Text(r) => if !raw_html[r].trim().is_empty() {
+                return None
+            },
+            HtmlToken::Tag(r) => match raw_html[r.clone()].parse() {
+                Ok(CustomHtmlTag::Inline(call)) => calls.push((call, r)),
+                Ok(CustomHtmlTag::Start(call)) => {
+                    has_start = true;
+                    calls.push((call, r))
+                },
+                _ => return None
+            }
+        }
+    }
+    // a single start tag is a component with children
+    if calls.is_empty() || (has_start && calls.len() == 1) {
+        None
+    }
+    else {
+        Some(calls)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -187,4 +233,54 @@ mod test {
                 )
         )
     }
+
+    #[test]
+    fn hyphenated_attributes(){
+        let c : CustomHtmlTag = "<Chart data-x=\"1\" aria-y = \"2\"/>".parse().unwrap();
+        assert_eq!(c, Inline(
+                ComponentCall {
+                    name: "Chart".into(),
+                    attributes: BTreeMap::from([
+                        ("aria-y".into(), "2".into()),
+                        ("data-x".into(), "1".into()),
+                    ])
+                },
+                )
+        );
+        assert!("<Chart a b=\"1\"/>".parse::<CustomHtmlTag>().is_err());
+        assert!("<Chart a<b=\"1\"/>".parse::<CustomHtmlTag>().is_err());
+    }
+
+    #[test]
+    fn attribute_entities(){
+        let c : CustomHtmlTag = "<Box label=\"&amp;foo &lt;3 &#169;&#x41;\" q=\"&quot;x&quot;\">".parse().unwrap();
+        assert_eq!(c, Start(
+                ComponentCall {
+                    name: "Box".into(),
+                    attributes: BTreeMap::from([
+                        ("label".into(), "&foo <3 \u{a9}A".into()),
+                        ("q".into(), "\"x\"".into()),
+                    ])
+                },
+                )
+        )
+    }
+
+    #[test]
+    fn parse_sequence(){
+        let calls = parse_component_sequence("<X/>").unwrap();
+        assert_eq!(calls.len(), 1);
+
+        let calls = parse_component_sequence("<X/> <Y k=\"v\"/>\n").unwrap();
+        assert_eq!(calls.iter().map(|(c, _)| c.name.as_str()).collect::<Vec<_>>(), vec!["X", "Y"]);
+        assert_eq!(calls.iter().map(|(_, r)| r.clone()).collect::<Vec<_>>(), vec![0..4, 5..16]);
+
+        let calls = parse_component_sequence("<X>\n<X a=\"b\">").unwrap();
+        assert_eq!(calls.iter().map(|(_, r)| r.clone()).collect::<Vec<_>>(), vec![0..3, 4..14]);
+
+        assert_eq!(parse_component_sequence("z<X/>"), None);
+        assert_eq!(parse_component_sequence("<X>"), None);
+        assert_eq!(parse_component_sequence("<X></X>"), None);
+        assert_eq!(parse_component_sequence(""), None);
+    }
 }
@@ -0,0 +1,274 @@
+/// the github emoji shortcodes, like `tada` for `:tada:`, sorted by shortcode
+const EMOJIS: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("1st_place_medal", "🥇"),
+    ("airplane", "✈️"),
+    ("alembic", "⚗️"),
+    ("alien", "👽"),
+    ("ambulance", "🚑"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("art", "🎨"),
+    ("astonished", "😲"),
+    ("balloon", "🎈"),
+    ("bar_chart", "📊"),
+    ("bee", "🐝"),
+    ("beer", "🍺"),
+    ("beers", "🍻"),
+    ("bell", "🔔"),
+    ("birthday", "🎂"),
+    ("black_heart", "🖤"),
+    ("blue_heart", "💙"),
+    ("blush", "😊"),
+    ("book", "📖"),
+    ("bookmark", "🔖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📆"),
+    ("cat", "🐱"),
+    ("chart_with_downwards_trend", "📉"),
+    ("chart_with_upwards_trend", "📈"),
+    ("checkered_flag", "🏁"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("clock1", "🕐"),
+    ("cloud", "☁️"),
+    ("clown_face", "🤡"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confetti_ball", "🎊"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("copyright", "©️"),
+    ("crab", "🦀"),
+    ("crown", "👑"),
+    ("cry", "😢"),
+    ("dart", "🎯"),
+    ("dog", "🐶"),
+    ("earth_africa", "🌍"),
+    ("email", "📧"),
+    ("evergreen_tree", "🌲"),
+    ("exclamation", "❗"),
+    ("exploding_head", "🤯"),
+    ("expressionless", "😑"),
+    ("eyes", "👀"),
+    ("facepalm", "🤦"),
+    ("fast_forward", "⏩"),
+    ("file_folder", "📁"),
+    ("fire", "🔥"),
+    ("flushed", "😳"),
+    ("four_leaf_clover", "🍀"),
+    ("gear", "⚙️"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("green_heart", "💚"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("handshake", "🤝"),
+    ("hear_no_evil", "🙉"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("heavy_check_mark", "✔️"),
+    ("heavy_exclamation_mark", "❗"),
+    ("heavy_minus_sign", "➖"),
+    ("heavy_plus_sign", "➕"),
+    ("hourglass", "⌛"),
+    ("hugs", "🤗"),
+    ("hushed", "😯"),
+    ("information_source", "ℹ️"),
+    ("innocent", "😇"),
+    ("iphone", "📱"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("kissing_heart", "😘"),
+    ("label", "🏷️"),
+    ("laughing", "😆"),
+    ("lemon", "🍋"),
+    ("link", "🔗"),
+    ("lipstick", "💄"),
+    ("lock", "🔒"),
+    ("loudspeaker", "📢"),
+    ("mag", "🔍"),
+    ("man_technologist", "👨‍💻"),
+    ("mask", "😷"),
+    ("medal_sports", "🏅"),
+    ("mega", "📣"),
+    ("memo", "📝"),
+    ("microscope", "🔬"),
+    ("money_with_wings", "💸"),
+    ("moneybag", "💰"),
+    ("monocle_face", "🧐"),
+    ("muscle", "💪"),
+    ("nerd_face", "🤓"),
+    ("neutral_face", "😐"),
+    ("no_entry", "⛔"),
+    ("no_entry_sign", "🚫"),
+    ("ok_hand", "👌"),
+    ("open_file_folder", "📂"),
+    ("open_mouth", "😮"),
+    ("orange_heart", "🧡"),
+    ("package", "📦"),
+    ("page_facing_up", "📄"),
+    ("paperclip", "📎"),
+    ("partying_face", "🥳"),
+    ("pencil2", "✏️"),
+    ("penguin", "🐧"),
+    ("pensive", "😔"),
+    ("pizza", "🍕"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("poop", "💩"),
+    ("pray", "🙏"),
+    ("purple_heart", "💜"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rabbit", "🐰"),
+    ("rage", "😡"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("registered", "®️"),
+    ("relieved", "😌"),
+    ("rewind", "⏪"),
+    ("robot", "🤖"),
+    ("rocket", "🚀"),
+    ("rose", "🌹"),
+    ("rotating_light", "🚨"),
+    ("satellite", "📡"),
+    ("scissors", "✂️"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("seedling", "🌱"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snake", "🐍"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("sparkling_heart", "💖"),
+    ("speak_no_evil", "🙊"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("star_struck", "🤩"),
+    ("stopwatch", "⏱️"),
+    ("stuck_out_tongue", "😛"),
+    ("sunflower", "🌻"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat", "😓"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("telescope", "🔭"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tm", "™️"),
+    ("triangular_flag_on_post", "🚩"),
+    ("trophy", "🏆"),
+    ("truck", "🚚"),
+    ("turtle", "🐢"),
+    ("umbrella", "☔"),
+    ("unamused", "😒"),
+    ("unicorn", "🦄"),
+    ("unlock", "🔓"),
+    ("upside_down_face", "🙃"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wastebasket", "🗑️"),
+    ("wave", "👋"),
+    ("whale", "🐳"),
+    ("white_check_mark", "✅"),
+    ("white_heart", "🤍"),
+    ("wink", "😉"),
+    ("woman_technologist", "👩‍💻"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yellow_heart", "💛"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zipper_mouth_face", "🤐"),
+];
+
+/// the emoji of the shortcode `shortcode`, like `🎉` for `tada`
+pub fn emoji(shortcode: &str) -> Option<&'static str> {
+    EMOJIS.binary_search_by_key(&shortcode, |(name, _)| *name)
+        .ok()
+        .map(|i| EMOJIS[i].1)
+}
+
+/// wether `c` can be part of a shortcode
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')
+}
+
+/// replaces the known shortcodes of `text`, like `:tada:`, by their emoji.
+/// Unknown shortcodes are kept as they are.
+/// Returns None if there is no shortcode to replace
+pub fn expand_shortcodes(text: &str) -> Option<String> {
+    let mut expanded = String::new();
+    // the part of `text` that is already in `expanded`
+    let mut copied = 0;
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start+1..];
+        let end = after.find(|c: char| !is_shortcode_char(c));
+        let found = match end {
+            Some(end) if end > 0 && after[end..].starts_with(':') => emoji(&after[..end])
+                .map(|e| (e, end)),
+            _ => None
+        };
+        match found {
+            Some((e, end)) => {
+                let offset = text.len() - rest.len();
+                expanded.push_str(&text[copied..offset+start]);
+                expanded.push_str(e);
+                copied = offset + start + end + 2;
+                rest = &after[end+1..];
+            },
+            None => rest = after,
+        }
+    }
+    if copied == 0 {
+        return None
+    }
+    expanded.push_str(&text[copied..]);
+    Some(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sorted_table(){
+        assert!(EMOJIS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn shortcodes(){
+        assert_eq!(expand_shortcodes("release :tada: :rocket:!").as_deref(), Some("release 🎉 🚀!"));
+        assert_eq!(expand_shortcodes(":+1::unknown: 12:30:00").as_deref(), Some("👍:unknown: 12:30:00"));
+        assert_eq!(expand_shortcodes("a :not_an_emoji: b"), None);
+        assert_eq!(expand_shortcodes("no shortcode"), None);
+    }
+}
@@ -0,0 +1,161 @@
+use core::ops::Range;
+use pulldown_cmark_wikilink::Tag;
+
+use crate::render::Renderer;
+use crate::utils::top_level_blocks;
+use crate::{extra_frontmatter, mount_katex_stylesheet, parse, Context, Event};
+
+/// The blocks of the last render, like paragraphs or lists,
+/// with their view, see [`render_markdown_incremental`].
+///
+/// The blocks are only compared by their markdown, not by the props they were rendered with:
+/// the cache must be cleared with [`BlockCache::clear`] when the props change,
+/// like the theme or the class map.
+pub struct BlockCache<V> {
+    /// the events of each block, formatted with their position in the source,
+    /// and the view of the block
+    blocks: Vec<(String, V)>,
+}
+
+impl<V> Default for BlockCache<V> {
+    fn default() -> Self {
+        Self { blocks: Vec::new() }
+    }
+}
+
+impl<V> BlockCache<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// forgets the views of the last render,
+    /// so that every block is rendered again by the next one
+    pub fn clear(&mut self) {
+        self.blocks.clear()
+    }
+}
+
+/// wether the events of a block can be rendered on their own.
+/// Html can open a custom component that contains the next blocks,
+/// and frontmatter is given to the context while rendering
+fn is_standalone(block: &[(Event, Range<usize>)]) -> bool {
+    !block.iter().any(|(e, _)| matches!(e,
+        Event::Html(_) | Event::InlineHtml(_)
+        | Event::Start(Tag::MetadataBlock(_))
+    ))
+}
+
+/// renders `source` like [`crate::render_markdown`], but reuses the views of `cache`
+/// for the first blocks, when they didn't change since the last render.
+///
+/// In an editor, the blocks before the edited one are not rendered again.
+/// The blocks after it are still rendered,
+/// because their position in the source changed.
+/// The rendering stops being incremental at the first block with html,
+/// since it can open a custom component that contains the next blocks.
+pub fn render_markdown_incremental<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    source: &'a str,
+    cache: &mut BlockCache<F::View>,
+    ) -> F::View
+{
+    if let Some((format, frontmatter, _)) = extra_frontmatter(source, &cx.props()) {
+        cx.set_frontmatter_with_format(frontmatter.to_string(), format);
+    }
+
+    let mut blocks = top_level_blocks(parse(source, &cx.props())).into_iter();
+    let mut elements = Vec::new();
+    let mut new_blocks = Vec::new();
+    // wether all the previous blocks are the same as in the last render
+    let mut same_prefix = true;
+
+    while let Some(block) = blocks.next() {
+        if !is_standalone(&block) {
+            // this block and the next ones are rendered together
+            let mut rest = block.into_iter().chain(blocks.by_ref().flatten());
+            elements.extend(Renderer::new(cx, source, &mut rest));
+            break
+        }
+        let key = format!("{block:?}");
+        let cached = cache.blocks.get(new_blocks.len())
+            .filter(|(k, _)| same_prefix && k == &key);
+        let view = match cached {
+            Some((_, view)) => view.clone(),
+            None => {
+                same_prefix = false;
+                let views = Renderer::new(cx, source, &mut block.into_iter()).collect();
+                cx.el_fragment(views)
+            }
+        };
+        elements.push(view.clone());
+        new_blocks.push((key, view));
+    }
+    cache.blocks = new_blocks;
+
+    mount_katex_stylesheet(cx);
+
+    cx.el_fragment(elements)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::default_props;
+    use crate::{render_to_html_string, MarkdownProps, StringContext};
+
+    fn render(source: &str, cache: &mut BlockCache<String>) -> String {
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        let html = render_markdown_incremental(StringContext::new(props), source, cache);
+        assert_eq!(html, render_to_html_string(source, props));
+        html
+    }
+
+    #[test]
+    fn edited_block(){
+        let mut cache = BlockCache::new();
+        render("# a\n\nb\n\nc", &mut cache);
+        assert_eq!(cache.blocks.len(), 3);
+        let first = cache.blocks[0].0.clone();
+        render("# a\n\nb\n\nd", &mut cache);
+        assert_eq!(cache.blocks[0].0, first);
+        render("# a\n\nbb\n\nd", &mut cache);
+        assert_eq!(cache.blocks.len(), 3);
+    }
+
+    #[test]
+    fn reused_views(){
+        // every wikilink that is rendered asks for its url
+        let rendered = std::cell::Cell::new(0);
+        let count = |page: &str, _: Option<&str>| {
+            rendered.set(rendered.get() + 1);
+            page.to_string()
+        };
+        let props = MarkdownProps {
+            wikilinks: true,
+            wikilink_resolver: Some(&count),
+            ..default_props()
+        };
+        let render = |source, cache: &mut BlockCache<String>| {
+            rendered.set(0);
+            render_markdown_incremental(StringContext::new(props), source, cache);
+            rendered.get()
+        };
+        let mut cache = BlockCache::new();
+        assert_eq!(render("[[a]]\n\n[[b]]\n\n[[c]]", &mut cache), 3);
+        assert_eq!(render("[[a]]\n\n[[b]]\n\n[[d]]", &mut cache), 1);
+        assert_eq!(render("[[a]]\n\n[[bb]]\n\n[[d]]", &mut cache), 2);
+        cache.clear();
+        assert_eq!(render("[[a]]\n\n[[bb]]\n\n[[d]]", &mut cache), 3);
+    }
+
+    #[test]
+    fn components_over_several_blocks(){
+        let mut cache = BlockCache::new();
+        render("a\n\n<X>\n\nb\n\n</X>\n\nc", &mut cache);
+        assert_eq!(cache.blocks.len(), 1);
+        render("a\n\n<X>\n\nb\n\n</X>\n\nd", &mut cache);
+    }
+}
@@ -1,21 +1,47 @@
-use pulldown_cmark_wikilink::{ParserOffsetIter, LinkType, Event};
-pub use pulldown_cmark_wikilink::{Options, CowStr};
+use pulldown_cmark_wikilink::{ParserOffsetIter, LinkType, MetadataBlockKind, Tag, TagEnd};
+pub use pulldown_cmark_wikilink::{Options, CowStr, Event};
 
 use core::ops::Range;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem::{discriminant, Discriminant};
+use std::rc::Rc;
 
 mod render;
 use render::Renderer;
+pub use render::{available_languages, available_themes};
 
 mod utils;
+pub use utils::{heading_plain_text, prefix_image_urls, slugify};
 
 mod component;
 
+mod cache;
+pub use cache::RenderCache;
+
+mod sanitize;
+pub use sanitize::SanitizeConfig;
+
+mod themes;
+pub use themes::ThemeRegistry;
+
+mod emoji;
+
+mod options;
+pub use options::MarkdownOptions;
+
+mod incremental;
+pub use incremental::{render_markdown_incremental, BlockCache};
+
+mod string_context;
+pub use string_context::{render_to_html_string, StringComponent, StringContext};
+
 
 pub struct ElementAttributes<H> {
     pub classes: Vec<String>,
     pub style: Option<String>,
-    pub on_click: Option<H>
+    pub on_click: Option<H>,
+    /// the `id` of the element, like the anchor of a heading
+    pub id: Option<String>,
 }
 
 impl<H> Default for ElementAttributes<H> {
@@ -23,29 +49,173 @@ impl<H> Default for ElementAttributes<H> {
         Self {
             style: None,
             classes: vec![],
-            on_click: None
+            on_click: None,
+            id: None
         }
     }
 }
 
+/// the kind of an element rendered by the markdown.
+/// It is also given to [`Context::make_md_handler`],
+/// so that a click can report which kind of element was clicked
+#[derive(Clone, Debug, PartialEq)]
 pub enum HtmlElement {
     Div,
     Span,
     Paragraph,
     BlockQuote,
     Ul,
-    Ol(i32),
+    /// an ordered list, rendered with the `start`, `reversed`
+    /// and `type` attributes given by its style
+    Ol(OrderedListStyle),
     Li,
+    /// a heading, with a level always between 1 and 6
     Heading(u8),
     Table,
     Thead,
+    Tbody,
     Trow,
     Tcell,
     Italics,
     Bold,
     StrikeThrough,
     Pre,
-    Code
+    Code,
+    Sub,
+    Sup,
+    Kbd,
+    Mark,
+    Abbr,
+    Underline,
+    /// a definition list, with terms and their definitions
+    Dl,
+    /// the term of a definition list
+    Dt,
+    /// a definition of the previous term
+    Dd,
+    /// a button, like the one that copies a code block
+    Button,
+    /// an image with its caption
+    Figure,
+    /// the caption of a figure
+    Figcaption,
+    /// the checkbox of a task
+    Input,
+    /// a formula, rendered inside a span
+    Math,
+    /// a horizontal rule
+    Hr,
+}
+
+/// Classes added to every element of a kind, like `table-striped` for tables,
+/// to style the markdown with a css framework.
+/// They are added to the classes that the element already has.
+#[derive(Default)]
+pub struct ClassMap {
+    classes: HashMap<Discriminant<HtmlElement>, Vec<String>>,
+    /// the classes of the headings, for each level
+    heading_classes: HashMap<u8, Vec<String>>,
+}
+
+impl ClassMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds the class `class` to every element like `element`.
+    /// Headings are matched by level, like `HtmlElement::Heading(2)` for `h2`,
+    /// the other elements by kind: `HtmlElement::Ol(_)` matches every ordered list
+    pub fn add(mut self, element: HtmlElement, class: impl ToString) -> Self {
+        let classes = match element {
+            HtmlElement::Heading(level) => self.heading_classes.entry(level).or_default(),
+            e => self.classes.entry(discriminant(&e)).or_default(),
+        };
+        classes.push(class.to_string());
+        self
+    }
+
+    /// the classes added to `element`
+    pub fn classes(&self, element: &HtmlElement) -> &[String] {
+        let classes = match element {
+            HtmlElement::Heading(level) => self.heading_classes.get(level),
+            e => self.classes.get(&discriminant(e)),
+        };
+        classes.map(|c| c.as_slice()).unwrap_or_default()
+    }
+}
+
+/// the numbering of the items of an ordered list
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ListKind {
+    /// 1, 2, 3
+    #[default]
+    Decimal,
+    /// a, b, c
+    LowerAlpha,
+    /// A, B, C
+    UpperAlpha,
+    /// i, ii, iii
+    LowerRoman,
+    /// I, II, III
+    UpperRoman,
+}
+
+impl ListKind {
+    /// the value of the `type` attribute of an `ol` element
+    pub fn html_type(self) -> &'static str {
+        match self {
+            ListKind::Decimal => "1",
+            ListKind::LowerAlpha => "a",
+            ListKind::UpperAlpha => "A",
+            ListKind::LowerRoman => "i",
+            ListKind::UpperRoman => "I",
+        }
+    }
+}
+
+/// how the items of an ordered list are numbered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrderedListStyle {
+    /// the number of the first item
+    pub start: i32,
+    /// wether the numbers decrease, like `3. 2. 1.`
+    pub reversed: bool,
+    /// the kind of numbers
+    pub kind: ListKind,
+}
+
+impl HtmlElement {
+    /// creates a heading of level `level`,
+    /// clamped between 1 and 6 so that it is always a valid html heading
+    pub fn heading(level: u8) -> Self {
+        HtmlElement::Heading(level.clamp(1, 6))
+    }
+
+    /// returns the element corresponding to the html tag `name`,
+    /// if it is one of the tags that can be listed in [`MarkdownProps::safe_tags`]
+    pub fn from_safe_tag(name: &str) -> Option<Self> {
+        Some(match name {
+            "sub" => HtmlElement::Sub,
+            "sup" => HtmlElement::Sup,
+            "kbd" => HtmlElement::Kbd,
+            "mark" => HtmlElement::Mark,
+            "abbr" => HtmlElement::Abbr,
+            "u" => HtmlElement::Underline,
+            "i" | "em" => HtmlElement::Italics,
+            "b" | "strong" => HtmlElement::Bold,
+            "s" | "del" => HtmlElement::StrikeThrough,
+            "code" => HtmlElement::Code,
+            "span" => HtmlElement::Span,
+            "div" => HtmlElement::Div,
+            "p" => HtmlElement::Paragraph,
+            "blockquote" => HtmlElement::BlockQuote,
+            "pre" => HtmlElement::Pre,
+            "dl" => HtmlElement::Dl,
+            "dt" => HtmlElement::Dt,
+            "dd" => HtmlElement::Dd,
+            _ => return None
+        })
+    }
 }
 
 pub trait Context<'a, 'callback>: Copy + 'a
@@ -62,14 +232,31 @@ where 'callback: 'a
     /// present at the top of the markdown source
     fn set_frontmatter(self, frontmatter: String);
 
+    /// write the frontmatter string, with its format.
+    /// By default, the format is ignored, see [`Context::set_frontmatter`]
+    fn set_frontmatter_with_format(self, frontmatter: String, _format: FrontmatterFormat) {
+        self.set_frontmatter(frontmatter)
+    }
+
     fn render_links(self, link: LinkDescription<Self::View>) 
         -> Result<Self::View, String>;
 
     /// calls a callback with the given input
     fn call_handler<T>(callback: &Self::Handler<T>, input: T);
 
-    /// creates a callback that will fire when the user clicks on markdown
-    fn make_md_handler(self, position: Range<usize>, stop_propagation: bool) -> Self::Handler<Self::MouseEvent>;
+    /// creates a callback that will fire when the user clicks on markdown.
+    /// `element` is the kind of the clicked element, like a heading or a code block,
+    /// that the mouse event should report
+    fn make_md_handler(self, element: &HtmlElement, position: Range<usize>, stop_propagation: bool) -> Self::Handler<Self::MouseEvent>;
+
+    /// creates the click handler of the element `element` at `position`.
+    /// The click stops propagating if `stop_propagation`,
+    /// unless [`MarkdownProps::stop_propagation`] decides otherwise
+    fn make_element_handler(self, element: &HtmlElement, position: Range<usize>, stop_propagation: bool)
+        -> Self::Handler<Self::MouseEvent> {
+        let stop_propagation = self.props().stops_propagation(element, stop_propagation);
+        self.make_md_handler(element, position, stop_propagation)
+    }
 
     #[cfg(feature="debug")]
     fn send_debug_info(self, info: Vec<String>);
@@ -84,9 +271,25 @@ where 'callback: 'a
         self.el_with_attributes(e, inside, Default::default())
     }
 
+    /// creates a html element of the markdown, like a paragraph or a table,
+    /// with the classes given to this kind of element by [`MarkdownProps::class_map`]
+    fn el_with_class_map(self, e: HtmlElement, inside: Self::View, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        let mut attributes = attributes;
+        if let Some(class_map) = self.props().class_map {
+            attributes.classes.extend(class_map.classes(&e).iter().cloned());
+        }
+        self.el_with_attributes(e, inside, attributes)
+    }
+
     /// renders raw html, inside a span
     fn el_span_with_inner_html(self, inner_html: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
 
+    /// renders a block of raw html, like `<div class="note">`, inside a div.
+    /// By default, it is the span of [`Context::el_span_with_inner_html`] inside a div
+    fn el_div_with_inner_html(self, inner_html: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        self.el_with_attributes(HtmlElement::Div, self.el_span_with_inner_html(inner_html, Default::default()), attributes)
+    }
+
     /// renders a `hr` element, with attributes
     fn el_hr(self, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
 
@@ -96,34 +299,139 @@ where 'callback: 'a
     /// takes a vector of views and return a view
     fn el_fragment(self, children: Vec<Self::View>) -> Self::View;
 
-    /// renders a link
-    fn el_a(self, children: Self::View, href: String) -> Self::View;
+    /// renders a link, with the `target`, `rel` and classes of `attributes`
+    fn el_a(self, children: Self::View, href: String, attributes: LinkAttributes) -> Self::View;
 
-    /// renders an image
-    fn el_img(self, src: String, alt: String) -> Self::View;
+    /// renders an image.
+    /// Decorative images should be rendered with `role="presentation"`,
+    /// see [`ImageAttributes`]
+    fn el_img(self, src: String, attributes: ImageAttributes) -> Self::View;
 
     /// renders an empty view
     fn el_empty(self) -> Self::View {
         self.el_fragment(vec![])
     }
 
-    /// renders raw text
+    /// renders raw text.
+    /// The text is never html: characters like `<` or `&` must be escaped
+    /// by the context, so that the user sees them as they are.
+    /// For example, the text of the code span `` `<script>` `` is `<script>`
     fn el_text(self, text: CowStr<'a>) -> Self::View;
 
+    /// renders a text that is not tracked by the click handler,
+    /// usually a whole paragraph of merged text.
+    /// By default, it is the same as [`Context::el_text`],
+    /// but a context can render it as a single text node.
+    fn el_plain_text(self, text: CowStr<'a>) -> Self::View {
+        self.el_text(text)
+    }
+
 
     // renders a checkbox with attributes
     fn el_input_checkbox(self, checked: bool, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
 
+    /// renders a checkbox that calls `on_change` with its new state when the user changes it.
+    /// By default, `on_change` is ignored: a context that creates change handlers,
+    /// see [`Context::make_change_handler`], should also render them
+    fn el_input_checkbox_with_change(self, checked: bool, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>, _on_change: Self::Handler<bool>) -> Self::View {
+        self.el_input_checkbox(checked, attributes)
+    }
+
 
     /// add a styleshit to the markdown component
     fn mount_dynamic_link(self, rel: &str, href: &str, integrity: &str, crossorigin: &str);
 
     fn has_custom_component(self, name: &str) -> bool;
+
+    /// renders the custom component `name`.
+    /// It is called synchronously, while the document is rendered,
+    /// and must return a view right away: a component that loads data
+    /// should return a view that loads it after the render.
+    /// An error is only for the inputs of the component, like a missing attribute,
+    /// since it is shown instead of the view
     fn render_custom_component(self, name: &str, input: MdComponentProps<Self::View>) -> Result<Self::View, ComponentCreationError>;
 
+    /// wether the children of the custom component `name` are rendered on demand,
+    /// with [`Context::render_custom_component_lazy`].
+    /// It is useful for components that don't always show their children, like tabs.
+    fn has_lazy_children(self, _name: &str) -> bool {
+        false
+    }
+
+    /// renders a custom component whose children are only rendered
+    /// when calling [`LazyChildren::render`].
+    /// By default, the children are rendered immediately.
+    fn render_custom_component_lazy(self, name: &str, input: MdComponentProps<LazyChildren<'a, Self::View>>) -> Result<Self::View, ComponentCreationError> {
+        self.render_custom_component(name, input.map_children(|c| c.render()))
+    }
+
+    /// renders a fenced code block written in `language`, like `mermaid`,
+    /// instead of highlighting it.
+    /// `code` is the content of the block, and `range` its position in the source.
+    /// If it returns `None`, the code block is highlighted as usual.
+    /// By default, `mermaid` diagrams are rendered as `<pre class="mermaid">`,
+    /// for the mermaid javascript library
+    fn render_fenced_block(self, language: &str, code: &str, range: Range<usize>) -> Option<Self::View> {
+        match language {
+            "mermaid" => {
+                let attributes = ElementAttributes {
+                    classes: vec!["mermaid".to_string()],
+                    on_click: Some(self.make_element_handler(&HtmlElement::Pre, range, true)),
+                    ..Default::default()
+                };
+                Some(self.el_with_attributes(HtmlElement::Pre, self.el_text(code.to_string().into()), attributes))
+            },
+            _ => None
+        }
+    }
+
+    /// creates a callback that copies `text` to the clipboard,
+    /// for the copy button of code blocks, see [`MarkdownProps::code_copy_button`].
+    /// By default, there is no callback, and code blocks don't have a copy button
+    fn make_copy_handler(self, _text: String) -> Option<Self::Handler<Self::MouseEvent>> {
+        None
+    }
+
+    /// called when an element can't be rendered, like an invalid formula
+    /// or a custom component that fails.
+    /// Returns the view shown instead of the element, for example an empty view
+    /// after logging the error.
+    /// If it returns `None`, the error is shown inline,
+    /// inside an element with the class `markdown-error`
+    fn on_error(self, _error: &HtmlError) -> Option<Self::View> {
+        None
+    }
+
+    /// creates a callback that will fire when the user toggles the checkbox of a task,
+    /// if [`MarkdownProps::editable_tasks`] is set.
+    /// The context should apply `edit` to the markdown source.
+    /// By default, it is the same as a click on the task marker
+    fn make_toggle_handler(self, edit: SourceEdit) -> Self::Handler<Self::MouseEvent> {
+        let end = edit.range.end + 1;
+        self.make_element_handler(&HtmlElement::Input, edit.range.start-1..end, true)
+    }
+
+    /// creates a callback that receives the new state of the checkbox of a task
+    /// when the user changes it, if [`MarkdownProps::editable_tasks`] is set.
+    /// The context should apply `SourceEdit::set_task(marker, checked)` to the markdown source,
+    /// where `marker` is the position of the marker, like `[ ]`.
+    /// By default there is none, and the checkbox uses [`Context::make_toggle_handler`] instead
+    fn make_change_handler(self, _marker: Range<usize>) -> Option<Self::Handler<bool>> {
+        None
+    }
+
     fn render_tasklist_marker(self, m: bool, position: Range<usize>) -> Self::View {
+        if self.props().editable_tasks {
+            if let Some(on_change) = self.make_change_handler(position.clone()) {
+                return self.el_input_checkbox_with_change(m, Default::default(), on_change)
+            }
+        }
+        let on_click = match self.props().editable_tasks {
+            true => self.make_toggle_handler(SourceEdit::toggle_task(position, m)),
+            false => self.make_element_handler(&HtmlElement::Input, position, true),
+        };
         let attributes = ElementAttributes {
-            on_click: Some(self.make_md_handler(position, true)),
+            on_click: Some(on_click),
             ..Default::default()
         };
         self.el_input_checkbox(m, attributes)
@@ -131,25 +439,32 @@ where 'callback: 'a
 
     fn render_rule(self, range: Range<usize>) -> Self::View {
         let attributes = ElementAttributes{
-            on_click: Some(self.make_md_handler(range, false)),
+            on_click: Some(self.make_element_handler(&HtmlElement::Hr, range, false)),
             ..Default::default()
         };
         self.el_hr(attributes)
     }
 
 
+    /// renders a code span.
+    /// `s` is the literal content of the code, that is rendered as text
     fn render_code(self, s: CowStr<'a>, range: Range<usize>) -> Self::View {
-        let callback = self.make_md_handler(range.clone(), false);
+        let callback = self.make_element_handler(&HtmlElement::Code, range.clone(), false);
         let attributes = ElementAttributes{
             on_click: Some(callback),
             ..Default::default()
         };
-        self.el_with_attributes(HtmlElement::Code, self.el_text(s), attributes)
+        self.el_with_class_map(HtmlElement::Code, self.el_text(s), attributes)
     }
 
 
-    fn render_text(self, s: CowStr<'a>, range: Range<usize>) -> Self::View{
-        let callback = self.make_md_handler(range, false);
+    /// renders a text inside the block `block`, like a paragraph or a heading.
+    /// A click on the text reports `block`
+    fn render_text(self, s: CowStr<'a>, range: Range<usize>, block: &HtmlElement) -> Self::View{
+        if !self.props().click_tracking {
+            return self.el_plain_text(s)
+        }
+        let callback = self.make_element_handler(block, range, false);
         let attributes = ElementAttributes{
             on_click: Some(callback),
             ..Default::default()
@@ -160,20 +475,89 @@ where 'callback: 'a
 
     fn has_custom_links(self) -> bool;
 
+    /// decides the attributes of each link, like `target` or `rel`.
+    /// By default, links don't have any attribute.
+    /// It is not used when the links are rendered with [`Context::render_links`]
+    fn link_attributes(self, _link: &LinkDescription<Self::View>) -> LinkAttributes {
+        LinkAttributes::default()
+    }
+
+
+    /// checks that an internal link, like `other-page.md`, points to something.
+    /// External links are never checked.
+    /// By default, every link exists.
+    fn link_exists(self, _url: &str) -> bool {
+        true
+    }
+
+    /// called when an internal link doesn't exist, see [`Context::link_exists`]
+    fn report_broken_link(self, _url: &str) {}
 
     fn render_link(self, link: LinkDescription<Self::View>) 
         -> Result<Self::View, String>
     {
+        let mut link = link;
+        if let (Some(resolve), Some(page)) = (self.props().wikilink_resolver, &link.wikilink) {
+            let text = Some(link.text.as_str()).filter(|t| *t != page.as_str());
+            link.url = resolve(page, text);
+        }
+
+        // a link like `javascript:alert(1)` is rendered as its content,
+        // an image as its alternative text
+        if !link.is_safe(self.props().allow_data_images) {
+            return match link.image {
+                true => Ok(self.el_text(link.text.into())),
+                false => Ok(link.content),
+            }
+        }
+
+        let broken = link.is_internal() && !self.link_exists(&link.url);
+        if broken {
+            self.report_broken_link(&link.url);
+        }
+
         if self.has_custom_links(){
             self.render_links(link)
         }
         else {
             Ok(
                 if link.image {
-                    self.el_img(link.url, link.title)
+                    let mut attributes = ImageAttributes::new(
+                        link.text,
+                        self.props().hide_decorative_images
+                    ).with_size(&link.title, &link.url);
+                    if self.props().lazy_images {
+                        attributes.loading = Some("lazy".to_string())
+                    }
+                    let caption = utils::image_caption(&link.title).to_string();
+                    let image = self.el_img(link.url, attributes);
+                    if self.props().figure_with_caption && !caption.is_empty() {
+                        let caption = self.el(HtmlElement::Figcaption, self.el_text(caption.into()));
+                        self.el(HtmlElement::Figure, self.el_fragment(vec![image, caption]))
+                    }
+                    else {
+                        image
+                    }
                 }
                 else {
-                    self.el_a(link.content, link.url)
+                    let mut attributes = self.link_attributes(&link);
+                    if attributes.title.is_none() && !link.title.is_empty() {
+                        attributes.title = Some(link.title)
+                    }
+                    if broken {
+                        attributes.classes.push("broken-link".to_string())
+                    }
+                    let props = self.props();
+                    let external = match props.site_url {
+                        Some(site_url) => link.is_external_to(site_url),
+                        None => link.is_external(),
+                    };
+                    if props.external_links_new_tab && external {
+                        let new_tab = LinkAttributes::new_tab();
+                        attributes.target = attributes.target.or(new_tab.target);
+                        attributes.rel = attributes.rel.or(new_tab.rel);
+                    }
+                    self.el_a(link.content, link.url, attributes)
                 }
             )
         }
@@ -182,6 +566,46 @@ where 'callback: 'a
 
 }
 
+/// the format of the frontmatter at the top of the markdown source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrontmatterFormat {
+    /// yaml, between `---` lines
+    Yaml,
+    /// toml, between `+++` lines
+    Toml,
+    /// json, between `--- json` and `---` lines
+    Json,
+}
+
+/// a change of the markdown source: replaces `range` by `replacement`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl SourceEdit {
+    /// checks or unchecks the task with the marker `marker`, like `[ ]`.
+    /// `checked` is the current state of the task
+    pub fn toggle_task(marker: Range<usize>, checked: bool) -> Self {
+        Self::set_task(marker, !checked)
+    }
+
+    /// checks the task with the marker `marker` if `checked`, and unchecks it otherwise.
+    /// `checked` is the new state of the task
+    pub fn set_task(marker: Range<usize>, checked: bool) -> Self {
+        Self {
+            range: marker.start+1..marker.start+2,
+            replacement: if checked {"x"} else {" "}.to_string(),
+        }
+    }
+
+    /// applies the change to `source`
+    pub fn apply(&self, source: &mut String) {
+        source.replace_range(self.range.clone(), &self.replacement)
+    }
+}
+
 /// the description of a link, used to render it with a custom callback.
 /// See [pulldown_cmark::Tag::Link] for documentation
 pub struct LinkDescription<V> {
@@ -195,20 +619,191 @@ pub struct LinkDescription<V> {
     /// If you don't know what it is, don't worry: it is ofter empty
     pub title: String,
 
+    /// the text under the link, without formatting.
+    /// For images, this is the alternative text
+    pub text: String,
+
+    /// the label of a reference-style link, like `label` in `[text][label]`.
+    /// It is empty for other links
+    pub id: String,
+
     /// the type of link
     pub link_type: LinkType,
 
     /// wether the link is an image
     pub image: bool,
+
+    /// the page of a wikilink, like `Page` in `[[Page|text]]`.
+    /// It is `None` for other links
+    pub wikilink: Option<String>,
+}
+
+impl<V> LinkDescription<V> {
+    /// wether the link points to an anchor inside the current page,
+    /// like `#section`
+    pub fn is_anchor(&self) -> bool {
+        self.url.starts_with('#')
+    }
+
+    /// wether the link points inside the current website,
+    /// with a relative or absolute path or an anchor
+    pub fn is_internal(&self) -> bool {
+        let path_end = self.url.find(|c: char| matches!(c, '/' | '?' | '#'))
+            .unwrap_or(self.url.len());
+        !self.url[..path_end].contains(':') && !self.url.starts_with("//")
+    }
+
+    /// wether the link points to another website
+    pub fn is_external(&self) -> bool {
+        let url = self.url.to_lowercase();
+        url.starts_with("http://")
+            || url.starts_with("https://")
+            || url.starts_with("//")
+    }
+
+    /// the scheme of the url, like `https` or `javascript`, in lowercase.
+    /// It is `None` for relative urls
+    pub fn scheme(&self) -> Option<String> {
+        utils::url_scheme(&self.url)
+    }
+
+    /// wether the url is safe to render: relative urls and
+    /// `http`, `https`, `mailto` and `tel` urls.
+    /// `data:image/...` urls are only safe for images, if `allow_data_images` is true
+    pub fn is_safe(&self, allow_data_images: bool) -> bool {
+        utils::is_safe_url(&self.url, self.image && allow_data_images)
+    }
+
+    /// wether the link points to another website than `site_url`,
+    /// like `https://example.com`
+    pub fn is_external_to(&self, site_url: &str) -> bool {
+        let url = self.url.to_lowercase();
+        let site_url = site_url.trim_end_matches('/').to_lowercase();
+        let same_site = match url.strip_prefix(&site_url) {
+            Some(rest) => rest.is_empty() || rest.starts_with(['/', '?', '#']),
+            None => false,
+        };
+        self.is_external() && !same_site
+    }
+}
+
+/// the attributes of a link, see [`Context::link_attributes`]
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct LinkAttributes {
+    /// the `target` attribute, for example `_blank`
+    pub target: Option<String>,
+
+    /// the `rel` attribute, for example `noopener noreferrer`
+    pub rel: Option<String>,
+
+    /// the `title` attribute.
+    /// If it is not set, the title of the markdown link is used
+    pub title: Option<String>,
+
+    /// the classes of the link
+    pub classes: Vec<String>,
+}
+
+impl LinkAttributes {
+    /// opens the link in a new tab,
+    /// without giving the new page access to the current one
+    pub fn new_tab() -> Self {
+        Self {
+            target: Some("_blank".to_string()),
+            rel: Some("noopener noreferrer".to_string()),
+            title: None,
+            classes: vec![],
+        }
+    }
+}
+
+
+/// the attributes of an image
+pub struct ImageAttributes {
+    /// the alternative text of the image.
+    /// It is empty for decorative images
+    pub alt: String,
+
+    /// wether the image is purely decorative.
+    /// In that case, it should be rendered with `role="presentation"`
+    pub decorative: bool,
+
+    /// wether the image should be hidden from assistive technologies,
+    /// with `aria-hidden="true"`
+    pub aria_hidden: bool,
+
+    /// the width of the image in pixels, see [`ImageAttributes::with_size`]
+    pub width: Option<u32>,
+
+    /// the height of the image in pixels
+    pub height: Option<u32>,
+
+    /// the `loading` attribute, `lazy` if [`MarkdownProps::lazy_images`] is set
+    pub loading: Option<String>,
+
+    /// the classes of the image
+    pub classes: Vec<String>,
+
+    /// the `style` attribute of the image
+    pub style: Option<String>,
 }
 
+impl ImageAttributes {
+    /// creates the attributes of an image with the alternative text `alt`.
+    /// An image without alternative text is decorative,
+    /// and is hidden from assistive technologies if `hide_decorative` is set.
+    pub fn new(alt: String, hide_decorative: bool) -> Self {
+        let decorative = alt.trim().is_empty();
+        Self {
+            alt: if decorative {String::new()} else {alt},
+            decorative,
+            aria_hidden: decorative && hide_decorative,
+            width: None,
+            height: None,
+            loading: None,
+            classes: vec![],
+            style: None,
+        }
+    }
 
-pub enum HtmlError {
+    /// sets the size of the image from the end of its title, like `=200x100`,
+    /// or from the `width` and `height` parameters of its url,
+    /// like `cat.png?width=200`
+    pub fn with_size(self, title: &str, url: &str) -> Self {
+        let (width, height) = utils::image_size(title, url);
+        Self { width, height, ..self }
+    }
+}
+
+
+/// an element of the markdown that can't be rendered,
+/// see [`Context::on_error`]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct HtmlError {
+    /// what went wrong
+    pub kind: HtmlErrorKind,
+    /// the position of the element in the markdown source
+    pub range: Range<usize>,
+}
+
+/// the reason why an element can't be rendered
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum HtmlErrorKind {
+    /// the element is not supported, like footnotes
     NotImplemented(String),
     Link(String),
+    /// invalid raw html, like a custom component without its closing tag
     Syntax(String),
+    /// a custom component that doesn't exist or failed to render
     CustomComponent{name: String, msg: String},
-    Math,
+    /// an invalid formula, with its source like `$\frac{1$`
+    Math(String),
+    /// the syntax highlighting theme doesn't exist
+    Theme(String),
+    /// the element is nested deeper than [`MarkdownProps::max_depth`]
+    TooDeep,
 }
 
 #[derive(PartialEq)]
@@ -232,12 +827,68 @@ pub enum HtmlError {
 ///     children: ... // html view of **hey**
 /// }
 /// ```
+///
+/// A component can contain other components, even with the same name,
+/// if each tag is alone in its html block, between blank lines.
+/// Several start tags in the same html block, like `<Card>` and `<Card>` on two lines,
+/// are components without children instead.
+///
+/// The html entities of the attribute values, like `&amp;` or `&#169;`, are decoded.
+/// The names of the attributes can contain letters, digits, `-`, `_`, `.` and `:`,
+/// like `data-user` or `aria-label`.
+/// By convention, the attributes starting with `data-` or `aria-`
+/// are meant for the root element of the component, see [`MdComponentProps::forwarded_attributes`].
+///
+/// The attribute `id` is also given to an element around the component,
+/// a `span` inside a paragraph and a `div` otherwise,
+/// so that every component can be linked to.
+/// It stays in the attributes, but the component should not give it to its own elements,
+/// since an id must be unique in the page.
 pub struct MdComponentProps<V> {
     pub attributes: BTreeMap<String, String>,
-    pub children: V
+    pub children: V,
+    /// the position of the tag of the component in the source
+    pub(crate) range: Range<usize>,
+    /// the position of the markdown inside the component in the source
+    pub(crate) inner_range: Range<usize>,
 }
 
 impl<V> MdComponentProps<V> {
+    /// creates the props of a component, for example to test it or to render it from another context.
+    /// `range` is the position of its tag in the source and `inner_range` the position of its children,
+    /// see [`MdComponentProps::range`] and [`MdComponentProps::inner_range`]
+    pub fn new(attributes: BTreeMap<String, String>, children: V,
+               range: Range<usize>, inner_range: Range<usize>) -> Self {
+        Self { attributes, children, range, inner_range }
+    }
+
+    /// the same props, with the children transformed by `f`,
+    /// like the [`LazyChildren`] that are rendered
+    pub fn map_children<W>(self, f: impl FnOnce(V) -> W) -> MdComponentProps<W> {
+        MdComponentProps {
+            attributes: self.attributes,
+            children: f(self.children),
+            range: self.range,
+            inner_range: self.inner_range,
+        }
+    }
+
+    /// returns the position of the tag that calls the component in the source,
+    /// like `<MyBox color="blue" size="5">`.
+    /// Inside a block quote or a list, it can be the position of the whole html block
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// returns the position of the markdown inside the component in the source,
+    /// from its first child to its last one, like `**hey !**`.
+    /// A component can read its children from the source,
+    /// to interpret them itself.
+    /// Without children, it is an empty range after the tag
+    pub fn inner_range(&self) -> Range<usize> {
+        self.inner_range.clone()
+    }
+
     /// returns the attribute string corresponding to the key `name`.
     /// returns None if the attribute was not provided
     pub fn get(&self, name: &str) -> Option<String> {
@@ -272,6 +923,49 @@ impl<V> MdComponentProps<V> {
             None => Ok(None)
         }
     }
+
+    /// returns the attributes starting with `data-` or `aria-`,
+    /// like `data-user="42"` or `aria-label="chart"`,
+    /// that the component should put on its root element as they are
+    pub fn forwarded_attributes(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.attributes.iter()
+            .filter(|(k, _)| k.starts_with("data-") || k.starts_with("aria-"))
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+
+/// the children of a custom component, that are only rendered when needed.
+/// See [`Context::has_lazy_children`]
+pub struct LazyChildren<'a, V> {
+    render: Rc<dyn Fn() -> V + 'a>,
+}
+
+impl<'a, V> LazyChildren<'a, V> {
+    pub(crate) fn new(render: impl Fn() -> V + 'a) -> Self {
+        Self {
+            render: Rc::new(render)
+        }
+    }
+
+    /// renders the children
+    pub fn render(&self) -> V {
+        (self.render)()
+    }
+}
+
+impl<'a, V> Clone for LazyChildren<'a, V> {
+    fn clone(&self) -> Self {
+        Self {
+            render: self.render.clone()
+        }
+    }
+}
+
+impl<'a, V> PartialEq for LazyChildren<'a, V> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.render, &other.render)
+    }
 }
 
 
@@ -289,15 +983,460 @@ impl<T: std::fmt::Debug> From<T> for ComponentCreationError {
 
 
 
+/// how to render the markdown elements that are not supported yet,
+/// like footnotes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Unsupported {
+    /// don't render anything
+    Skip,
+    /// render an error
+    #[default]
+    Error,
+    /// render the markdown source of the element as text
+    RawText,
+}
+
+/// how to render a tag like `<Widget/>` when the component
+/// `Widget` is not registered by the context.
+/// Only the capitalised tags are taken for components:
+/// an unknown tag like `<div>` is raw html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UnknownComponent {
+    /// render an error
+    #[default]
+    Error,
+    /// render the tag as raw html
+    RawHtml,
+    /// render the name of the component, in a span
+    /// with the class `unknown-component`
+    Placeholder,
+}
+
+/// how the soft breaks are rendered:
+/// the line breaks inside a paragraph, that are not hard breaks
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SoftBreakMode {
+    /// a space, like the newlines of html
+    #[default]
+    Space,
+    /// a newline in the text,
+    /// shown as a line break with the css `white-space: pre-line`
+    Newline,
+    /// nothing, the lines are joined
+    Ignore,
+}
+
+/// the markup generated by katex for the formulas
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MathOutput {
+    /// html styled by the katex stylesheet
+    HtmlOnly,
+    /// mathml, that is read by screen readers
+    MathMlOnly,
+    /// html, with mathml for screen readers
+    #[default]
+    HtmlAndMathMl,
+}
+
+/// how the code blocks are highlighted
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HighlightMode {
+    /// highlight the code with syntect, with the theme of [`MarkdownProps::theme`]
+    #[default]
+    Syntect,
+    /// render the code as `<pre><code class="language-x">`,
+    /// for a javascript library like Prism or highlight.js.
+    /// It is also much faster for live previews
+    ClientSide,
+}
+
+
+#[derive(Clone, Copy)]
 pub struct MarkdownProps<'a>
 {
+    /// render soft breaks as `<br>` inside paragraphs and list items.
+    /// Headings and table cells are not affected.
     pub hard_line_breaks: bool,
 
+    /// how the other soft breaks are rendered, see [`SoftBreakMode`]
+    pub soft_break: SoftBreakMode,
+
     pub wikilinks: bool,
 
+    /// gives the url of a wikilink, from the page and the text of the link,
+    /// like `/docs/installation` for `[[Installation]]`.
+    /// The text is `None` if it is the name of the page.
+    /// If `None`, the url is the name of the page
+    pub wikilink_resolver: Option<&'a dyn Fn(&str, Option<&str>) -> String>,
+
+    /// the options of the markdown parser.
+    /// If `None`, the options are [`DEFAULT_PARSE_OPTIONS`], including smart punctuation:
+    /// it can be disabled with `parse_options_remove`.
+    /// The replaced characters, like `---` for `—`, keep their range in the source
     pub parse_options: Option<&'a pulldown_cmark_wikilink::Options>,
 
+    /// options enabled on top of `parse_options`,
+    /// so that the defaults don't have to be repeated
+    pub parse_options_add: Options,
+
+    /// options disabled from `parse_options`
+    pub parse_options_remove: Options,
+
+    /// the name of the syntax highlighting theme,
+    /// either registered in `themes` or one of the default themes of syntect,
+    /// listed by [`available_themes`]
     pub theme: Option<&'a str>,
+
+    /// the name of the syntax highlighting theme used in dark mode.
+    /// If set, each code block is highlighted twice, with `theme`
+    /// inside a `div` with the class `light-theme`,
+    /// and with this theme inside a `div` with the class `dark-theme`.
+    /// The page shows one of them with css, for example with `prefers-color-scheme`.
+    /// It doubles the size of the highlighted code in the page.
+    /// Otherwise, the theme can be switched by rendering again
+    /// with another `theme`, see [`MarkdownProps::theme_for`]
+    pub dark_theme: Option<&'a str>,
+
+    /// custom syntax highlighting themes, see [`ThemeRegistry`]
+    pub themes: Option<&'a ThemeRegistry>,
+
+    /// the syntax highlighting theme itself, used instead of `theme`,
+    /// like a theme built by the app or loaded at startup.
+    /// The code highlighted with it is not put in the [`RenderCache`]
+    pub theme_value: Option<&'a syntect::highlighting::Theme>,
+
+    /// how the code blocks are highlighted, see [`HighlightMode`]
+    pub highlight_mode: HighlightMode,
+
+    /// the grammars used to highlight the code blocks,
+    /// for example a smaller set dumped with syntect, with only a few languages.
+    /// They must be loaded with newlines, like `SyntaxSet::load_defaults_newlines`.
+    /// If `None`, every default grammar of syntect is loaded
+    /// when the first code block is highlighted
+    pub syntax_set: Option<&'a syntect::parsing::SyntaxSet>,
+
+    /// expand the tabs that indent the lines of code blocks
+    /// to this number of spaces, before highlighting.
+    /// If `None`, the tabs are kept
+    pub code_tab_width: Option<usize>,
+
+    /// show the line numbers of code blocks, in a gutter next to the code
+    pub code_line_numbers: bool,
+
+    /// add a button that copies the source of each code block,
+    /// in the top right corner of the block.
+    /// It needs a context that implements [`Context::make_copy_handler`]
+    pub code_copy_button: bool,
+
+    /// highlight the inline code that starts with its language,
+    /// like `` `rust:let x = 1` ``, with the theme of the code blocks.
+    /// The language is removed from the code.
+    /// If the language is unknown, the code is rendered as is
+    pub inline_code_highlight: bool,
+
+    /// the html tags that are allowed inside the markdown, like `sub` or `div`.
+    /// They are rendered as the corresponding [`HtmlElement`],
+    /// with their inner markdown and their `class` attribute.
+    /// Every other tag is stripped, but its content is kept.
+    /// If `None`, raw html is given to the context as is.
+    pub safe_tags: Option<&'a HashSet<&'a str>>,
+
+    /// the tags and attributes allowed in the raw html
+    /// that is given to the context as is, when `safe_tags` is `None`.
+    /// If `None`, the raw html is not sanitized
+    pub sanitize: Option<&'a SanitizeConfig>,
+
+    /// turn the urls and email addresses of the text into links,
+    /// like `https://example.com`, `www.example.com` or `me@example.com`.
+    /// Autolinks like `<https://example.com>` are always links
+    pub linkify: bool,
+
+    /// open the links to other websites in a new tab,
+    /// with `rel="noopener noreferrer"`
+    pub external_links_new_tab: bool,
+
+    /// the url of the current website, like `https://example.com`.
+    /// Absolute links to this url are not external
+    pub site_url: Option<&'a str>,
+
+    /// allow images with a `data:image/...` url.
+    /// Links and images with another scheme than `http`, `https`, `mailto` or `tel`
+    /// are never rendered
+    pub allow_data_images: bool,
+
+    /// add `aria-hidden="true"` to images without alternative text
+    pub hide_decorative_images: bool,
+
+    /// load the images only when they are about to be shown,
+    /// with `loading="lazy"`
+    pub lazy_images: bool,
+
+    /// render the images with a title, like `![a cat](cat.png "My cat")`,
+    /// inside a `figure`, with the title as its `figcaption`.
+    /// Such an image alone in its paragraph is rendered without the paragraph
+    pub figure_with_caption: bool,
+
+    /// render a paragraph that only contains custom components,
+    /// like `<X/>` or `<X/><Y/>`, as the components alone, without the paragraph.
+    /// A paragraph that also contains text, like `z<X/>`, is always kept
+    pub unwrap_component_paragraphs: bool,
+
+    /// render the custom components without children that are alone in their html block,
+    /// like `<X/>`, or `<X/>` and `<Y/>` on their own lines, inside a paragraph,
+    /// like the components that follow text, as in `z<X/>`.
+    /// A line like `<X/><Y/>` is already a paragraph
+    pub wrap_block_components: bool,
+
+    /// toggling the checkbox of a task, like `- [ ] a`, edits the source,
+    /// see [`Context::make_toggle_handler`]
+    pub editable_tasks: bool,
+
+    /// the numbering of ordered lists
+    pub ordered_list_kind: ListKind,
+
+    /// added to the level of every heading, at most `h6`,
+    /// for markdown shown under a heading of the page:
+    /// with an offset of 1, `#` is rendered as `h2`
+    pub heading_offset: u8,
+
+    /// the maximum number of nested elements, like block quotes inside block quotes.
+    /// The elements nested deeper are replaced by an error,
+    /// instead of overflowing the stack
+    pub max_depth: usize,
+
+    /// classes added to every element of a kind, see [`ClassMap`]
+    pub class_map: Option<&'a ClassMap>,
+
+    /// how to render the elements that are not supported yet, like footnotes
+    pub unsupported: Unsupported,
+
+    /// how to render the components that are not registered
+    pub unknown_component: UnknownComponent,
+
+    /// creates the anchor id of a heading from its text.
+    /// Duplicate ids get a `-1`, `-2`... suffix.
+    /// If `None`, [`slugify`] is used
+    pub slugify: Option<&'a dyn Fn(&str) -> String>,
+
+    /// replace the github emoji shortcodes of the text, like `:tada:`, by their emoji.
+    /// Unknown shortcodes are kept
+    pub emoji: bool,
+
+    /// latex macros available in every formula, like `\RR` for `\mathbb{R}`.
+    /// An invalid macro is shown as an invalid formula
+    pub math_macros: Option<&'a BTreeMap<String, String>>,
+
+    /// allow the formulas to use commands like `\href`
+    pub math_trust: bool,
+
+    /// the markup of the formulas, see [`MathOutput`]
+    pub math_output: MathOutput,
+
+    /// wether the katex stylesheet used by the formulas is added to the page.
+    /// Disable it if the app serves the stylesheet itself,
+    /// for example when the content security policy blocks the cdn
+    pub mount_math_stylesheet: bool,
+
+    /// the url of the katex stylesheet that is added to the page,
+    /// instead of the one from the jsdelivr cdn.
+    /// It is added without integrity check
+    pub math_stylesheet_href: Option<&'a str>,
+
+    /// data kept between renders, see [`RenderCache`]
+    pub cache: Option<&'a RenderCache>,
+
+    /// make the rendered elements call the markdown click handler.
+    /// If false, adjacent texts are merged and rendered with
+    /// [`Context::el_plain_text`], without any span around them
+    pub click_tracking: bool,
+
+    /// decides if a click on an element of a kind stops propagating,
+    /// or also reaches the elements around it, like the container of the markdown.
+    /// If `None`, the clicks on code blocks, formulas, task markers and errors
+    /// stop propagating, and the others propagate
+    pub stop_propagation: Option<&'a dyn Fn(&HtmlElement) -> bool>,
+
+    /// functions that edit the parsed events before they are rendered,
+    /// like [`prefix_image_urls`]. They are called in order,
+    /// on the events of the whole document,
+    /// after the other options, like `linkify` or `hard_line_breaks`.
+    /// The ranges of the events must stay positions in the source
+    pub event_transforms: &'a [&'a EventTransform<'a>],
+}
+
+/// a function that edits the events of a document before they are rendered,
+/// see [`MarkdownProps::event_transforms`]
+pub type EventTransform<'a> = dyn for<'e> Fn(&mut Vec<(Event<'e>, Range<usize>)>) + 'a;
+
+impl<'a> MarkdownProps<'a> {
+    /// the options used to parse the markdown:
+    /// `parse_options` (or the default options),
+    /// with `parse_options_add` and without `parse_options_remove`
+    pub fn effective_parse_options(&self) -> Options {
+        let base = self.parse_options.copied().unwrap_or(DEFAULT_PARSE_OPTIONS);
+        (base | self.parse_options_add) & !self.parse_options_remove
+    }
+
+    /// wether a click on `element` stops propagating,
+    /// see [`MarkdownProps::stop_propagation`].
+    /// `default` is the choice of the renderer for this element
+    pub fn stops_propagation(&self, element: &HtmlElement, default: bool) -> bool {
+        match self.stop_propagation {
+            Some(f) => f(element),
+            None => default,
+        }
+    }
+
+    /// the syntax highlighting theme for the dark mode if `dark` is true,
+    /// `dark_theme` if it is set, and `theme` otherwise.
+    /// Rendering with this `theme` and no `dark_theme`
+    /// highlights the code blocks only once
+    pub fn theme_for(&self, dark: bool) -> Option<&'a str> {
+        match dark {
+            true => self.dark_theme.or(self.theme),
+            false => self.theme,
+        }
+    }
+}
+
+/// the options of the parser used when [`MarkdownProps::parse_options`] is not set:
+/// every extension that the renderer handles.
+/// Footnotes are not rendered yet, so `[^1]` stays as text unless they are added.
+/// The options are listed explicitly, so that the new options of the parser
+/// are not enabled by surprise when it is updated
+pub const DEFAULT_PARSE_OPTIONS: Options = Options::ENABLE_TABLES
+    .union(Options::ENABLE_STRIKETHROUGH)
+    .union(Options::ENABLE_TASKLISTS)
+    .union(Options::ENABLE_SMART_PUNCTUATION)
+    .union(Options::ENABLE_HEADING_ATTRIBUTES)
+    .union(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS)
+    .union(Options::ENABLE_MATH);
+
+/// parses `source` into a stream of events,
+/// with the options given by `props`.
+/// The events are parsed lazily, while they are rendered
+pub(crate) fn parse<'a>(source: &'a str, props: &MarkdownProps<'a>)
+    -> Box<dyn Iterator<Item=(Event<'a>, Range<usize>)> + 'a> {
+    let options = props.effective_parse_options();
+    // the parser only knows yaml frontmatter,
+    // the others are skipped before parsing the rest of the source
+    let offset = match extra_frontmatter(source, props) {
+        Some((_, _, end)) => end,
+        None => 0,
+    };
+    let mut stream: Box<dyn Iterator<Item=_> + 'a>
+        = Box::new(
+            ParserOffsetIter::new_ext(&source[offset..], options, props.wikilinks)
+                .map(move |(e, range)| (e, range.start+offset..range.end+offset))
+        );
+
+    if props.hard_line_breaks {
+        stream = Box::new(utils::soft_to_hard_breaks(stream));
+    }
+
+    stream = Box::new(utils::replace_soft_breaks(stream, props.soft_break));
+
+    let explicit_ids = utils::explicit_heading_ids(&source[offset..], options);
+    stream = Box::new(utils::HeadingIds::new(stream, props.slugify.unwrap_or(&slugify), explicit_ids));
+
+    if props.linkify {
+        stream = Box::new(utils::Linkify::new(stream));
+    }
+
+    if !props.click_tracking {
+        stream = Box::new(utils::MergedTexts::new(stream));
+    }
+
+    if !props.event_transforms.is_empty() {
+        let mut events: Vec<_> = stream.collect();
+        for transform in props.event_transforms {
+            transform(&mut events);
+        }
+        stream = Box::new(events.into_iter());
+    }
+
+    stream
+}
+
+/// the toml or json frontmatter of `source`, with its format, its content
+/// and the end of the frontmatter, if the props enable metadata blocks
+fn extra_frontmatter<'s>(source: &'s str, props: &MarkdownProps) -> Option<(FrontmatterFormat, &'s str, usize)> {
+    props.effective_parse_options()
+        .contains(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS)
+        .then(|| utils::extra_frontmatter(source))
+        .flatten()
+}
+
+/// a heading of the document, as listed in a table of contents
+#[derive(Clone, Debug, PartialEq)]
+pub struct TocEntry {
+    /// the level of the heading, between 1 and 6
+    pub level: u8,
+    /// the text of the heading, without formatting
+    pub text: String,
+    /// the anchor id of the heading
+    pub slug: String,
+    /// the position of the heading in the source
+    pub range: Range<usize>,
+}
+
+/// lists the headings of `source`, to render a table of contents
+/// next to the markdown.
+/// The slugs are the ids of the rendered headings, when
+/// [`MarkdownProps::slugify`] is not set.
+/// If `options` is `None`, they are [`DEFAULT_PARSE_OPTIONS`], like when rendering.
+/// The levels are shifted by `heading_offset`, see [`MarkdownProps::heading_offset`]
+pub fn table_of_contents(source: &str, options: Option<Options>, heading_offset: u8) -> Vec<TocEntry> {
+    let options = options.unwrap_or(DEFAULT_PARSE_OPTIONS);
+    let stream: Vec<_> = utils::HeadingIds::new(
+        ParserOffsetIter::new_ext(source, options, false),
+        &slugify,
+        utils::explicit_heading_ids(source, options)
+    ).collect();
+
+    let mut entries = Vec::new();
+    for (i, (e, range)) in stream.iter().enumerate() {
+        if let Event::Start(Tag::Heading{level, id, ..}) = e {
+            let end = utils::heading_end(&stream, i, *level);
+            entries.push(TocEntry {
+                level: utils::offset_heading_level(*level, heading_offset),
+                text: heading_plain_text(&stream[i+1..end]),
+                slug: id.as_ref().map(|x| x.to_string()).unwrap_or_default(),
+                range: range.clone(),
+            });
+        }
+    }
+    entries
+}
+
+/// the events of `source`, with their position in the source,
+/// exactly as they are rendered with `props`.
+/// They can be transformed, and then rendered with [`render_markdown_events`]
+pub fn events<'a>(source: &'a str, props: &MarkdownProps<'a>) -> Vec<(Event<'a>, Range<usize>)> {
+    parse(source, props).collect()
+}
+
+/// the yaml frontmatter of `source`, between the `---` at the top of the document.
+/// It is the string given to [`Context::set_frontmatter`]
+pub fn frontmatter(source: &str) -> Option<String> {
+    let mut stream = ParserOffsetIter::new_ext(source, DEFAULT_PARSE_OPTIONS, false);
+    match stream.next() {
+        Some((Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)), _)) => Some(
+            utils::collect_text(&mut stream, TagEnd::MetadataBlock(MetadataBlockKind::YamlStyle))
+        ),
+        _ => None
+    }
+}
+
+/// parses the yaml frontmatter of `source`, like a `title` or `tags`.
+/// Returns `Ok(None)` if there is no frontmatter.
+/// Only yaml is parsed: the toml and json frontmatters are ignored,
+/// but they are given to [`Context::set_frontmatter_with_format`]
+#[cfg(feature="serde")]
+pub fn parse_frontmatter<T: serde::de::DeserializeOwned>(source: &str) -> Result<Option<T>, serde_yaml::Error> {
+    frontmatter(source)
+        .map(|x| serde_yaml::from_str(&x))
+        .transpose()
 }
 
 pub fn render_markdown<'a, 'callback, F: Context<'a, 'callback>>(
@@ -305,36 +1444,499 @@ pub fn render_markdown<'a, 'callback, F: Context<'a, 'callback>>(
     source: &'a str, 
     ) -> F::View 
 {
+    render_markdown_events(cx, source, parse(source, &cx.props()))
+}
 
-    let parse_options_default = Options::all();
-    let options = cx.props().parse_options.unwrap_or(&parse_options_default);
-    let mut stream: Vec<_>
-        = ParserOffsetIter::new_ext(source, *options, cx.props().wikilinks).collect();
+/// renders the events of `source`, like the ones returned by [`events`]
+pub fn render_markdown_events<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F, 
+    source: &'a str, 
+    events: impl IntoIterator<Item=(Event<'a>, Range<usize>)>,
+    ) -> F::View 
+{
+    if let Some((format, frontmatter, _)) = extra_frontmatter(source, &cx.props()) {
+        cx.set_frontmatter_with_format(frontmatter.to_string(), format);
+    }
+
+    let stream = events.into_iter();
 
     #[cfg(feature="debug")]
-    {
+    let stream = {
+        let stream: Vec<_> = stream.collect();
         let debug_info : Vec<String> = stream.iter().map(|x| format!("{:?}", x)).collect();
-        cx.send_debug_info(debug_info)
+        cx.send_debug_info(debug_info);
+        stream.into_iter()
+    };
+
+    let elements = Renderer::new(cx, source, &mut stream.into_iter())
+        .collect::<Vec<_>>();
+
+    mount_katex_stylesheet(cx);
+
+    cx.el_fragment(elements)
+}
+
+/// the stylesheet of katex on a cdn, mounted by default
+const KATEX_STYLESHEET: &str = "https://cdn.jsdelivr.net/npm/katex@0.16.7/dist/katex.min.css";
+
+/// the hash of [`KATEX_STYLESHEET`]
+const KATEX_INTEGRITY: &str = "sha384-3UiQGuEI4TTMaFmGIZumfRPtfKQ3trwQE2JgosJxCnGmQpL/lJdjpcHkaaFwHlcI";
+
+/// the href, integrity and crossorigin of the katex stylesheet to mount,
+/// or None if the props don't mount it
+fn math_stylesheet<'a>(props: &MarkdownProps<'a>) -> Option<(&'a str, &'a str, &'a str)> {
+    if !props.mount_math_stylesheet {
+        return None
     }
+    Some(match props.math_stylesheet_href {
+        Some(href) => (href, "", ""),
+        None => (KATEX_STYLESHEET, KATEX_INTEGRITY, "anonymous"),
+    })
+}
 
-    if cx.props().hard_line_breaks {
-        for (r, _) in &mut stream {
-            if *r == Event::SoftBreak {
-                *r = Event::HardBreak
-            }
+/// adds the stylesheet of katex, used by the formulas
+fn mount_katex_stylesheet<'a, 'callback, F: Context<'a, 'callback>>(cx: F) {
+    if let Some((href, integrity, crossorigin)) = math_stylesheet(&cx.props()) {
+        cx.mount_dynamic_link("stylesheet", href, integrity, crossorigin);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pulldown_cmark_wikilink::Tag;
+
+    fn link(url: &str) -> LinkDescription<()> {
+        LinkDescription {
+            url: url.to_string(),
+            content: (),
+            title: String::new(),
+            text: String::new(),
+            id: String::new(),
+            link_type: LinkType::Inline,
+            image: false,
+            wikilink: None,
         }
     }
 
-    let elements = Renderer::new(cx, &mut stream.into_iter())
-        .collect::<Vec<_>>();
+    pub(crate) fn default_props() -> MarkdownProps<'static> {
+        MarkdownProps {
+            hard_line_breaks: false,
+            soft_break: SoftBreakMode::Space,
+            wikilinks: false,
+            wikilink_resolver: None,
+            parse_options: None,
+            parse_options_add: Options::empty(),
+            parse_options_remove: Options::empty(),
+            theme: None,
+            dark_theme: None,
+            themes: None,
+            theme_value: None,
+            highlight_mode: HighlightMode::Syntect,
+            syntax_set: None,
+            code_tab_width: None,
+            code_line_numbers: false,
+            code_copy_button: false,
+            inline_code_highlight: false,
+            safe_tags: None,
+            sanitize: None,
+            linkify: false,
+            external_links_new_tab: false,
+            site_url: None,
+            allow_data_images: false,
+            hide_decorative_images: false,
+            lazy_images: false,
+            figure_with_caption: false,
+            unwrap_component_paragraphs: false,
+            wrap_block_components: false,
+            editable_tasks: false,
+            ordered_list_kind: ListKind::Decimal,
+            heading_offset: 0,
+            max_depth: 32,
+            class_map: None,
+            unsupported: Unsupported::Error,
+            unknown_component: UnknownComponent::Error,
+            slugify: None,
+            emoji: false,
+            math_macros: None,
+            math_trust: false,
+            math_output: MathOutput::HtmlAndMathMl,
+            mount_math_stylesheet: true,
+            math_stylesheet_href: None,
+            cache: None,
+            click_tracking: true,
+            stop_propagation: None,
+            event_transforms: &[],
+        }
+    }
 
+    #[test]
+    fn literal_carets(){
+        // the parser has no superscripts yet: this keeps them off when it gets them
+        let props = default_props();
+        assert_eq!(props.effective_parse_options(), DEFAULT_PARSE_OPTIONS);
+        let texts: String = events("x^2^ and y^a b^", &props).into_iter()
+            .filter_map(|(e, _)| match e {
+                Event::Text(s) => Some(s.to_string()),
+                _ => None
+            })
+            .collect();
+        assert_eq!(texts, "x^2^ and y^a b^");
+    }
 
-    cx.mount_dynamic_link(
-        "stylesheet",
-        "https://cdn.jsdelivr.net/npm/katex@0.16.7/dist/katex.min.css",
-        "sha384-3UiQGuEI4TTMaFmGIZumfRPtfKQ3trwQE2JgosJxCnGmQpL/lJdjpcHkaaFwHlcI",
-        "anonymous"
-    );
+    #[test]
+    fn footnotes_off_by_default(){
+        let source = "a[^1]\n\n[^1]: b";
+        assert!(events(source, &default_props()).iter()
+            .all(|(e, _)| !matches!(e, Event::FootnoteReference(_) | Event::Start(Tag::FootnoteDefinition(_)))));
 
-    cx.el_fragment(elements)
+        let props = MarkdownProps {
+            parse_options_add: Options::ENABLE_FOOTNOTES,
+            ..default_props()
+        };
+        assert!(events(source, &props).iter().any(|(e, _)| matches!(e, Event::FootnoteReference(_))));
+    }
+
+    #[test]
+    fn additive_parse_options(){
+        let base = Options::ENABLE_TABLES;
+        let props = MarkdownProps {
+            parse_options: Some(&base),
+            parse_options_add: Options::ENABLE_STRIKETHROUGH,
+            ..default_props()
+        };
+        assert_eq!(props.effective_parse_options(), Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH);
+
+        let props = MarkdownProps {
+            parse_options_remove: Options::ENABLE_FOOTNOTES,
+            ..default_props()
+        };
+        assert!(!props.effective_parse_options().contains(Options::ENABLE_FOOTNOTES));
+        assert!(props.effective_parse_options().contains(Options::ENABLE_TABLES));
+    }
+
+    #[test]
+    fn reference_link(){
+        let source = "[a][1]\n\n[1]: http://x \"t\"";
+        let link = ParserOffsetIter::new_ext(source, Options::all(), false)
+            .find_map(|(e, _)| match e {
+                Event::Start(Tag::Link{link_type, dest_url, title, id}) =>
+                    Some((link_type, dest_url.to_string(), title.to_string(), id.to_string())),
+                _ => None
+            });
+        assert_eq!(link, Some((LinkType::Reference, "http://x".into(), "t".into(), "1".into())));
+    }
+
+    #[test]
+    fn link_kinds(){
+        assert!(link("#section").is_anchor());
+        assert!(!link("#section").is_external());
+        assert!(link("https://example.com").is_external());
+        assert!(!link("/docs/page").is_external());
+        assert!(!link("/docs/page").is_anchor());
+        assert!(link("/docs/page").is_internal());
+        assert!(link("missing.md").is_internal());
+        assert!(link("#section").is_internal());
+        assert!(!link("https://example.com/a:b").is_internal());
+        assert!(!link("mailto:me@example.com").is_internal());
+        assert!(!link("//example.com").is_internal());
+    }
+
+    #[test]
+    fn unsafe_links(){
+        assert!(!link("javascript:alert(1)").is_safe(true));
+        assert!(!link(" JavaScript:alert(1)").is_safe(true));
+        assert!(!link("java\tscript:alert(1)").is_safe(true));
+        assert!(link("https://example.com/a:b").is_safe(false));
+        assert!(link("mailto:me@example.com").is_safe(false));
+        assert!(link("docs/a.md#b").is_safe(false));
+        assert!(!link("data:text/html,<script>alert(1)</script>").is_safe(true));
+    }
+
+    #[test]
+    fn data_images(){
+        let image = |url| LinkDescription { image: true, ..link(url) };
+        assert!(!image("data:text/html;base64,PHNjcmlwdD4=").is_safe(true));
+        assert!(!image("data:image/png;base64,iVBORw0K").is_safe(false));
+        assert!(image("data:image/png;base64,iVBORw0K").is_safe(true));
+    }
+
+    #[test]
+    fn own_site_links(){
+        assert!(!link("https://example.com/docs").is_external_to("https://example.com/"));
+        assert!(!link("https://Example.com").is_external_to("https://example.com"));
+        assert!(link("https://example.com.evil.org").is_external_to("https://example.com"));
+        assert!(link("https://other.org").is_external_to("https://example.com"));
+        assert!(!link("#section").is_external_to("https://example.com"));
+    }
+
+    #[test]
+    fn toc(){
+        let entries = table_of_contents("# A *b*\ntext\n\n## c\n# A b", None, 0);
+        let slugs: Vec<_> = entries.iter().map(|x| (x.level, x.slug.as_str())).collect();
+        assert_eq!(slugs, vec![(1, "a-b"), (2, "c"), (1, "a-b-1")]);
+        assert_eq!(entries[0].text, "A b");
+        assert_eq!(entries[0].range.start, 0);
+    }
+
+    #[test]
+    fn parsed_events(){
+        let props = MarkdownProps {
+            hard_line_breaks: true,
+            ..default_props()
+        };
+        let events = events("# A\nb\nc", &props);
+        assert!(matches!(&events[0].0, Event::Start(Tag::Heading{id: Some(id), ..}) if &**id == "a"));
+        assert!(events.iter().any(|(e, _)| e == &Event::HardBreak));
+        assert!(!events.iter().any(|(e, _)| e == &Event::SoftBreak));
+    }
+
+    #[test]
+    fn yaml_frontmatter(){
+        assert_eq!(frontmatter("---\ntitle: a\n---\n# b").as_deref(), Some("title: a\n"));
+        assert_eq!(frontmatter("# b\n---\ntitle: a\n---\n"), None);
+    }
+
+    #[cfg(feature="serde")]
+    #[test]
+    fn typed_frontmatter(){
+        let parsed: Option<BTreeMap<String, String>> = parse_frontmatter("---\ntitle: a\n---\n# b").unwrap();
+        assert_eq!(parsed.unwrap()["title"], "a");
+        assert!(parse_frontmatter::<BTreeMap<String, String>>("# b").unwrap().is_none());
+        assert!(parse_frontmatter::<BTreeMap<String, String>>("---\ntitle: [a\n---\n").is_err());
+    }
+
+    #[test]
+    fn toc_heading_offset(){
+        let levels: Vec<_> = table_of_contents("# a\n## b\n###### c", None, 1)
+            .iter()
+            .map(|x| x.level)
+            .collect();
+        assert_eq!(levels, vec![2, 3, 6]);
+    }
+
+    #[test]
+    fn smart_punctuation_ranges(){
+        let source = "a --- b...";
+        let texts: Vec<_> = events(source, &default_props())
+            .into_iter()
+            .filter_map(|(e, range)| match e {
+                Event::Text(s) => Some((s.to_string(), &source[range])),
+                _ => None
+            })
+            .collect();
+        assert!(texts.contains(&("\u{2014}".to_string(), "---")));
+        assert!(texts.contains(&(" b".to_string(), " b")));
+        assert!(texts.contains(&("\u{2026}".to_string(), "...")));
+
+        let props = MarkdownProps {
+            parse_options_remove: Options::ENABLE_SMART_PUNCTUATION,
+            ..default_props()
+        };
+        assert!(events(source, &props).iter().all(|(e, _)| !matches!(e, Event::Text(s) if s.contains('\u{2014}'))));
+    }
+
+    #[test]
+    fn event_transforms(){
+        let cdn = prefix_image_urls("https://cdn.a.b/");
+        let no_rules = |events: &mut Vec<(Event, Range<usize>)>| events.retain(|(e, _)| *e != Event::Rule);
+        let transforms: [&EventTransform; 2] = [&cdn, &no_rules];
+        let props = MarkdownProps {
+            event_transforms: &transforms,
+            ..default_props()
+        };
+        let events = events("![a](a.png) ![b](https://b.c/b.png) ![c](/c.png)\n\n---", &props);
+        let urls: Vec<_> = events.iter()
+            .filter_map(|(e, _)| match e {
+                Event::Start(Tag::Image{dest_url, ..}) => Some(dest_url.to_string()),
+                _ => None
+            })
+            .collect();
+        assert_eq!(urls, vec!["https://cdn.a.b/a.png", "https://b.c/b.png", "/c.png"]);
+        assert!(!events.iter().any(|(e, _)| *e == Event::Rule));
+    }
+
+    #[test]
+    fn wikilinks_with_parse_options(){
+        let options = Options::empty();
+        let props = MarkdownProps {
+            wikilinks: true,
+            parse_options: Some(&options),
+            ..default_props()
+        };
+        let links = events("[[Page]]", &props).into_iter()
+            .filter(|(e, _)| matches!(e, Event::Start(Tag::Link{..})))
+            .count();
+        assert_eq!(links, 1);
+    }
+
+    #[test]
+    fn forwarded_attributes(){
+        let props = MdComponentProps {
+            attributes: BTreeMap::from([
+                ("data-user".to_string(), "42".to_string()),
+                ("aria-label".to_string(), "a".to_string()),
+                ("color".to_string(), "blue".to_string()),
+            ]),
+            children: (),
+            range: 0..0,
+            inner_range: 0..0,
+        };
+        assert_eq!(props.forwarded_attributes().collect::<Vec<_>>(),
+                   vec![("aria-label", "a"), ("data-user", "42")]);
+    }
+
+    #[test]
+    fn constructed_props(){
+        let attributes = BTreeMap::from([("color".to_string(), "blue".to_string())]);
+        let props = MdComponentProps::new(attributes, 2, 0..14, 14..20)
+            .map_children(|c| c * 10);
+        assert_eq!(props.children, 20);
+        assert_eq!(props.get("color").as_deref(), Some("blue"));
+        assert_eq!((props.range(), props.inner_range()), (0..14, 14..20));
+    }
+
+    #[test]
+    fn math_stylesheets(){
+        let props = default_props();
+        assert_eq!(math_stylesheet(&props), Some((KATEX_STYLESHEET, KATEX_INTEGRITY, "anonymous")));
+        let props = MarkdownProps {
+            math_stylesheet_href: Some("/katex.css"),
+            ..props
+        };
+        assert_eq!(math_stylesheet(&props), Some(("/katex.css", "", "")));
+        let props = MarkdownProps {
+            mount_math_stylesheet: false,
+            ..props
+        };
+        assert_eq!(math_stylesheet(&props), None);
+    }
+
+    #[test]
+    fn propagation_policy(){
+        let props = default_props();
+        assert!(props.stops_propagation(&HtmlElement::Pre, true));
+        assert!(!props.stops_propagation(&HtmlElement::Span, false));
+
+        let only_code_blocks = |e: &HtmlElement| matches!(e, HtmlElement::Pre);
+        let props = MarkdownProps {
+            stop_propagation: Some(&only_code_blocks),
+            ..default_props()
+        };
+        assert!(props.stops_propagation(&HtmlElement::Pre, false));
+        assert!(!props.stops_propagation(&HtmlElement::Code, true));
+    }
+
+    #[test]
+    fn theme_pair(){
+        let props = MarkdownProps {
+            theme: Some("base16-ocean.light"),
+            dark_theme: Some("base16-ocean.dark"),
+            ..default_props()
+        };
+        assert_eq!(props.theme_for(false), Some("base16-ocean.light"));
+        assert_eq!(props.theme_for(true), Some("base16-ocean.dark"));
+        let props = MarkdownProps {
+            dark_theme: None,
+            ..props
+        };
+        assert_eq!(props.theme_for(true), Some("base16-ocean.light"));
+    }
+
+    #[test]
+    fn linkified_ranges(){
+        let props = MarkdownProps {
+            linkify: true,
+            ..default_props()
+        };
+        let source = "a https://a.b c";
+        let links: Vec<_> = events(source, &props).into_iter()
+            .filter(|(e, _)| matches!(e, Event::Start(Tag::Link{..})))
+            .map(|(_, range)| &source[range])
+            .collect();
+        assert_eq!(links, vec!["https://a.b"]);
+    }
+
+    #[test]
+    fn toggle_nested_tasks(){
+        let mut source = "- [ ] a\n  - [x] b\n    - [ ] c\n".to_string();
+        let markers: Vec<_> = ParserOffsetIter::new_ext(&source, Options::all(), false)
+            .filter_map(|(e, range)| match e {
+                Event::TaskListMarker(checked) => Some((checked, range)),
+                _ => None
+            })
+            .collect();
+        assert_eq!(markers.len(), 3);
+        for (checked, range) in markers {
+            SourceEdit::toggle_task(range, checked).apply(&mut source);
+        }
+        assert_eq!(source, "- [x] a\n  - [ ] b\n    - [x] c\n");
+    }
+
+    #[test]
+    fn set_tasks(){
+        let mut source = "- [ ] a\n- [x] b\n".to_string();
+        SourceEdit::set_task(2..5, true).apply(&mut source);
+        SourceEdit::set_task(10..13, true).apply(&mut source);
+        assert_eq!(source, "- [x] a\n- [x] b\n");
+        SourceEdit::set_task(10..13, false).apply(&mut source);
+        assert_eq!(source, "- [x] a\n- [ ] b\n");
+    }
+
+    #[test]
+    fn clamped_heading(){
+        assert!(matches!(HtmlElement::heading(0), HtmlElement::Heading(1)));
+        assert!(matches!(HtmlElement::heading(3), HtmlElement::Heading(3)));
+        assert!(matches!(HtmlElement::heading(9), HtmlElement::Heading(6)));
+    }
+
+    #[test]
+    fn definition_list_tags(){
+        assert!(matches!(HtmlElement::from_safe_tag("dl"), Some(HtmlElement::Dl)));
+        assert!(matches!(HtmlElement::from_safe_tag("dt"), Some(HtmlElement::Dt)));
+        assert!(matches!(HtmlElement::from_safe_tag("dd"), Some(HtmlElement::Dd)));
+    }
+
+    #[test]
+    fn class_map(){
+        let class_map = ClassMap::new()
+            .add(HtmlElement::Heading(2), "prose-h2")
+            .add(HtmlElement::Table, "table")
+            .add(HtmlElement::Table, "table-striped")
+            .add(HtmlElement::Ol(OrderedListStyle{start: 1, reversed: false, kind: ListKind::Decimal}), "list");
+        assert_eq!(class_map.classes(&HtmlElement::Heading(2)), ["prose-h2"]);
+        assert!(class_map.classes(&HtmlElement::Heading(1)).is_empty());
+        assert_eq!(class_map.classes(&HtmlElement::Table), ["table", "table-striped"]);
+        let reversed = OrderedListStyle{start: 3, reversed: true, kind: ListKind::UpperRoman};
+        assert_eq!(class_map.classes(&HtmlElement::Ol(reversed)), ["list"]);
+        assert!(class_map.classes(&HtmlElement::Tcell).is_empty());
+    }
+
+    #[test]
+    fn alphabetic_list(){
+        assert_eq!(ListKind::LowerAlpha.html_type(), "a");
+        assert_eq!(ListKind::UpperRoman.html_type(), "I");
+        assert_eq!(ListKind::default().html_type(), "1");
+    }
+
+    #[test]
+    fn decorative_image(){
+        let attributes = ImageAttributes::new(" ".into(), false);
+        assert_eq!(attributes.alt, "");
+        assert!(attributes.decorative);
+        assert!(!attributes.aria_hidden);
+
+        let attributes = ImageAttributes::new("".into(), true);
+        assert!(attributes.decorative);
+        assert!(attributes.aria_hidden);
+    }
+
+    #[test]
+    fn described_image(){
+        let attributes = ImageAttributes::new("a cat".into(), true);
+        assert_eq!(attributes.alt, "a cat");
+        assert!(!attributes.decorative);
+        assert!(!attributes.aria_hidden);
+    }
 }
@@ -0,0 +1,473 @@
+use std::collections::{BTreeMap, HashSet};
+
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+use crate::{
+    ClassMap,
+    EventTransform,
+    HighlightMode,
+    HtmlElement,
+    ListKind,
+    MarkdownProps,
+    MathOutput,
+    Options,
+    RenderCache,
+    SanitizeConfig,
+    SoftBreakMode,
+    ThemeRegistry,
+    UnknownComponent,
+    Unsupported,
+};
+
+/// A builder for [`MarkdownProps`], starting from the default options:
+/// every parser option, syntect highlighting, click tracking,
+/// a maximum depth of 32 nested elements,
+/// and every other feature disabled.
+///
+/// ```rust
+/// # use rust_web_markdown::{MarkdownOptions, MarkdownProps};
+/// let props: MarkdownProps = MarkdownOptions::new()
+///     .hard_line_breaks(true)
+///     .theme("base16-ocean.dark")
+///     .into();
+/// ```
+/// Each setter is documented on the field of [`MarkdownProps`] with the same name.
+#[derive(Clone, Copy)]
+pub struct MarkdownOptions<'a> {
+    props: MarkdownProps<'a>,
+}
+
+impl<'a> Default for MarkdownOptions<'a> {
+    fn default() -> Self {
+        Self {
+            props: MarkdownProps {
+                hard_line_breaks: false,
+                soft_break: SoftBreakMode::Space,
+                wikilinks: false,
+                wikilink_resolver: None,
+                parse_options: None,
+                parse_options_add: Options::empty(),
+                parse_options_remove: Options::empty(),
+                theme: None,
+                dark_theme: None,
+                themes: None,
+                theme_value: None,
+                highlight_mode: HighlightMode::Syntect,
+                syntax_set: None,
+                code_tab_width: None,
+                code_line_numbers: false,
+                code_copy_button: false,
+                inline_code_highlight: false,
+                safe_tags: None,
+                sanitize: None,
+                linkify: false,
+                external_links_new_tab: false,
+                site_url: None,
+                allow_data_images: false,
+                hide_decorative_images: false,
+                lazy_images: false,
+                figure_with_caption: false,
+                unwrap_component_paragraphs: false,
+                wrap_block_components: false,
+                editable_tasks: false,
+                ordered_list_kind: ListKind::Decimal,
+                heading_offset: 0,
+                max_depth: 32,
+                class_map: None,
+                unsupported: Unsupported::Error,
+                unknown_component: UnknownComponent::Error,
+                slugify: None,
+                emoji: false,
+                math_macros: None,
+                math_trust: false,
+                math_output: MathOutput::HtmlAndMathMl,
+                mount_math_stylesheet: true,
+                math_stylesheet_href: None,
+                cache: None,
+                click_tracking: true,
+                stop_propagation: None,
+                event_transforms: &[],
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static!{
+    /// no html tag is allowed, see [`MarkdownOptions::comment_safe`]
+    static ref NO_TAGS: HashSet<&'static str> = HashSet::new();
+}
+
+impl<'a> MarkdownOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the syntax of commonmark only, like emphasis, links, lists or code,
+    /// without any extension of the parser:
+    /// no tables, footnotes, strikethrough, task lists, smart punctuation,
+    /// heading attributes or metadata blocks
+    pub fn minimal() -> Self {
+        Self::new().parse_options_remove(Options::all())
+    }
+
+    /// for markdown written by the users of a website, like comments:
+    /// commonmark with strikethrough and task lists.
+    /// The raw html tags are stripped, but their text is kept,
+    /// and the tags of unknown components, like `<Widget/>`, are shown as text
+    pub fn comment_safe() -> Self {
+        let enabled = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        Self::new()
+            .parse_options_remove(Options::all() - enabled)
+            .safe_tags(&NO_TAGS)
+            .unknown_component(UnknownComponent::Placeholder)
+    }
+
+    /// every option of the parser, and wikilinks
+    pub fn full() -> Self {
+        Self::new()
+            .parse_options_add(Options::all())
+            .wikilinks(true)
+    }
+
+    /// the props with these options
+    pub fn build(self) -> MarkdownProps<'a> {
+        self.props
+    }
+
+    /// sets [`MarkdownProps::hard_line_breaks`]
+    pub fn hard_line_breaks(mut self, value: bool) -> Self {
+        self.props.hard_line_breaks = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::soft_break`]
+    pub fn soft_break(mut self, value: SoftBreakMode) -> Self {
+        self.props.soft_break = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::wikilinks`]
+    pub fn wikilinks(mut self, value: bool) -> Self {
+        self.props.wikilinks = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::wikilink_resolver`]
+    pub fn wikilink_resolver(mut self, value: &'a dyn Fn(&str, Option<&str>) -> String) -> Self {
+        self.props.wikilink_resolver = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::parse_options`]
+    pub fn parse_options(mut self, value: &'a Options) -> Self {
+        self.props.parse_options = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::parse_options_add`]
+    pub fn parse_options_add(mut self, value: Options) -> Self {
+        self.props.parse_options_add = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::parse_options_remove`]
+    pub fn parse_options_remove(mut self, value: Options) -> Self {
+        self.props.parse_options_remove = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::theme`]
+    pub fn theme(mut self, value: &'a str) -> Self {
+        self.props.theme = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::dark_theme`]
+    pub fn dark_theme(mut self, value: &'a str) -> Self {
+        self.props.dark_theme = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::themes`]
+    pub fn themes(mut self, value: &'a ThemeRegistry) -> Self {
+        self.props.themes = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::theme_value`]
+    pub fn theme_value(mut self, value: &'a Theme) -> Self {
+        self.props.theme_value = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::highlight_mode`]
+    pub fn highlight_mode(mut self, value: HighlightMode) -> Self {
+        self.props.highlight_mode = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::syntax_set`]
+    pub fn syntax_set(mut self, value: &'a SyntaxSet) -> Self {
+        self.props.syntax_set = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::code_tab_width`]
+    pub fn code_tab_width(mut self, value: usize) -> Self {
+        self.props.code_tab_width = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::code_line_numbers`]
+    pub fn code_line_numbers(mut self, value: bool) -> Self {
+        self.props.code_line_numbers = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::code_copy_button`]
+    pub fn code_copy_button(mut self, value: bool) -> Self {
+        self.props.code_copy_button = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::inline_code_highlight`]
+    pub fn inline_code_highlight(mut self, value: bool) -> Self {
+        self.props.inline_code_highlight = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::safe_tags`]
+    pub fn safe_tags(mut self, value: &'a HashSet<&'a str>) -> Self {
+        self.props.safe_tags = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::sanitize`]
+    pub fn sanitize(mut self, value: &'a SanitizeConfig) -> Self {
+        self.props.sanitize = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::linkify`]
+    pub fn linkify(mut self, value: bool) -> Self {
+        self.props.linkify = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::external_links_new_tab`]
+    pub fn external_links_new_tab(mut self, value: bool) -> Self {
+        self.props.external_links_new_tab = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::site_url`]
+    pub fn site_url(mut self, value: &'a str) -> Self {
+        self.props.site_url = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::allow_data_images`]
+    pub fn allow_data_images(mut self, value: bool) -> Self {
+        self.props.allow_data_images = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::hide_decorative_images`]
+    pub fn hide_decorative_images(mut self, value: bool) -> Self {
+        self.props.hide_decorative_images = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::lazy_images`]
+    pub fn lazy_images(mut self, value: bool) -> Self {
+        self.props.lazy_images = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::figure_with_caption`]
+    pub fn figure_with_caption(mut self, value: bool) -> Self {
+        self.props.figure_with_caption = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::unwrap_component_paragraphs`]
+    pub fn unwrap_component_paragraphs(mut self, value: bool) -> Self {
+        self.props.unwrap_component_paragraphs = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::wrap_block_components`]
+    pub fn wrap_block_components(mut self, value: bool) -> Self {
+        self.props.wrap_block_components = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::editable_tasks`]
+    pub fn editable_tasks(mut self, value: bool) -> Self {
+        self.props.editable_tasks = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::ordered_list_kind`]
+    pub fn ordered_list_kind(mut self, value: ListKind) -> Self {
+        self.props.ordered_list_kind = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::heading_offset`]
+    pub fn heading_offset(mut self, value: u8) -> Self {
+        self.props.heading_offset = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::max_depth`]
+    pub fn max_depth(mut self, value: usize) -> Self {
+        self.props.max_depth = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::class_map`]
+    pub fn class_map(mut self, value: &'a ClassMap) -> Self {
+        self.props.class_map = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::unsupported`]
+    pub fn unsupported(mut self, value: Unsupported) -> Self {
+        self.props.unsupported = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::unknown_component`]
+    pub fn unknown_component(mut self, value: UnknownComponent) -> Self {
+        self.props.unknown_component = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::slugify`]
+    pub fn slugify(mut self, value: &'a dyn Fn(&str) -> String) -> Self {
+        self.props.slugify = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::emoji`]
+    pub fn emoji(mut self, value: bool) -> Self {
+        self.props.emoji = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::math_macros`]
+    pub fn math_macros(mut self, value: &'a BTreeMap<String, String>) -> Self {
+        self.props.math_macros = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::math_trust`]
+    pub fn math_trust(mut self, value: bool) -> Self {
+        self.props.math_trust = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::math_output`]
+    pub fn math_output(mut self, value: MathOutput) -> Self {
+        self.props.math_output = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::mount_math_stylesheet`]
+    pub fn mount_math_stylesheet(mut self, value: bool) -> Self {
+        self.props.mount_math_stylesheet = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::math_stylesheet_href`]
+    pub fn math_stylesheet_href(mut self, value: &'a str) -> Self {
+        self.props.math_stylesheet_href = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::cache`]
+    pub fn cache(mut self, value: &'a RenderCache) -> Self {
+        self.props.cache = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::click_tracking`]
+    pub fn click_tracking(mut self, value: bool) -> Self {
+        self.props.click_tracking = value;
+        self
+    }
+
+    /// sets [`MarkdownProps::stop_propagation`]
+    pub fn stop_propagation(mut self, value: &'a dyn Fn(&HtmlElement) -> bool) -> Self {
+        self.props.stop_propagation = Some(value);
+        self
+    }
+
+    /// sets [`MarkdownProps::event_transforms`]
+    pub fn event_transforms(mut self, value: &'a [&'a EventTransform<'a>]) -> Self {
+        self.props.event_transforms = value;
+        self
+    }
+}
+
+impl<'a> From<MarkdownOptions<'a>> for MarkdownProps<'a> {
+    fn from(options: MarkdownOptions<'a>) -> Self {
+        options.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{render_to_html_string, DEFAULT_PARSE_OPTIONS};
+    use crate::test::default_props;
+
+    #[test]
+    fn same_defaults(){
+        let source = "# a\n\n- [x] *b*\n\n```rust\nfn c(){}\n```\n\n[d](https://e.f) <Widget/>";
+        assert_eq!(render_to_html_string(source, MarkdownOptions::new().build()),
+                   render_to_html_string(source, default_props()));
+
+        let props = MarkdownOptions::new().build();
+        assert_eq!(props.effective_parse_options(), DEFAULT_PARSE_OPTIONS);
+        assert!(props.click_tracking);
+        assert_eq!(props.highlight_mode, HighlightMode::Syntect);
+    }
+
+    #[test]
+    fn presets(){
+        assert_eq!(MarkdownOptions::minimal().build().effective_parse_options(), Options::empty());
+
+        let props = MarkdownOptions::comment_safe().build();
+        assert_eq!(props.effective_parse_options(),
+                   Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS);
+        let props = MarkdownOptions::comment_safe().click_tracking(false).build();
+        assert_eq!(render_to_html_string("~~a~~ <b>c</b>", props), "<p><s>a</s> c</p>");
+        assert_eq!(render_to_html_string("| d |\n|---|", props), "<p>| d | |---|</p>");
+        assert_eq!(render_to_html_string("<div>\na\n</div>\n", props), "<p>a</p>");
+        assert_eq!(render_to_html_string("a\n\n<b>\n\nc", props), "<p>a</p><p>c</p>");
+        assert_eq!(render_to_html_string("<Widget/>\n", props),
+                   "<span class=\"unknown-component\">&lt;Widget&gt;</span>");
+
+        let props = MarkdownOptions::full().build();
+        assert_eq!(props.effective_parse_options(), Options::all());
+        assert!(props.wikilinks);
+    }
+
+    #[test]
+    fn setters(){
+        let props: MarkdownProps = MarkdownOptions::new()
+            .hard_line_breaks(true)
+            .theme("base16-ocean.dark")
+            .code_tab_width(4)
+            .heading_offset(1)
+            .into();
+        assert!(props.hard_line_breaks);
+        assert_eq!(props.theme, Some("base16-ocean.dark"));
+        assert_eq!(props.code_tab_width, Some(4));
+        assert_eq!(props.heading_offset, 1);
+        assert!(!props.wikilinks);
+    }
+}
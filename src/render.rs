@@ -1,30 +1,53 @@
-use core::ops::Range;
+use core::ops::{Range, RangeInclusive};
 
 use core::marker::PhantomData;
 
 use syntect::parsing::SyntaxSet;
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::easy::HighlightLines;
+use syntect::html::{start_highlighted_html_snippet, styled_line_to_highlighted_html, IncludeBackground};
+use syntect::util::LinesWithEndings;
 
-use pulldown_cmark_wikilink::{Event, Tag, TagEnd, CodeBlockKind, Alignment};
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, CodeBlockKind, Alignment, LinkType};
 
-#[cfg(features="maths")]
+#[cfg(any(feature="maths", feature="client-maths"))]
 use pulldown_cmark_wikilink::MathMode;
+#[cfg(feature="maths")]
+use crate::MathOutput;
 
-#[cfg(features="maths")]
-use katex;
 
-use crate::utils::as_closing_tag;
+use crate::utils::{as_closing_tag, code_block_title, collect_events, collect_text, component_events,
+                   escape_html, expand_leading_tabs, highlighted_lines, inline_code_language, is_captioned_image,
+                   is_reversed_list,
+                   offset_heading_level,
+                   only_inline_html, plain_text, split_table_head, strip_admonition, strip_html_tags,
+                   tokenize_html, wikilink_page, ComponentEnd, HtmlToken};
+use crate::sanitize::sanitize;
+use crate::emoji::expand_shortcodes;
+use crate::cache::CodeKey;
 use super::{
     Context,
+    CowStr,
+    LazyChildren,
     LinkDescription,
+    MarkdownProps,
     MdComponentProps,
     ElementAttributes,
-    HtmlError
+    FrontmatterFormat,
+    HtmlError,
+    HtmlErrorKind,
+    HighlightMode,
+    OrderedListStyle,
+    Unsupported,
+    UnknownComponent,
 };
 
+use super::HtmlElement;
 use super::HtmlElement::*;
 
-use crate::component::{ComponentCall, CustomHtmlTag};
+use crate::component::{ComponentCall, CustomHtmlTag, parse_component_sequence};
+
+use std::collections::HashSet;
 
 // load the default syntect options to highlight code
 lazy_static::lazy_static!{
@@ -38,83 +61,340 @@ lazy_static::lazy_static!{
 
 
 
-impl HtmlError {
+impl HtmlErrorKind {
     fn not_implemented(message: impl ToString) -> Self{
-        HtmlError::NotImplemented(message.to_string())
+        HtmlErrorKind::NotImplemented(message.to_string())
     }
     fn syntax(message: impl ToString) -> Self{
-        HtmlError::Syntax(message.to_string())
+        HtmlErrorKind::Syntax(message.to_string())
     }
     fn component(name: impl ToString, msg: impl ToString) -> Self {
-        HtmlError::CustomComponent{
+        HtmlErrorKind::CustomComponent{
             name: name.to_string(),
             msg: msg.to_string(),
         }
     }
 }
 
-impl ToString for HtmlError {
-    fn to_string(&self) -> String {
+impl core::fmt::Display for HtmlErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
-            HtmlError::Math => "invalid math".to_string(),
-            HtmlError::NotImplemented(s) => 
-                format!("`{s}`: not implemented"),
-            HtmlError::CustomComponent{name, msg} =>
-                format!("Custom component `{name}` failed: `{msg}`"),
-            HtmlError::Syntax(s) =>
-                format!("syntax error: {s}"),
-            HtmlError::Link(s) =>
-                format!("invalid link: {s}"),
+            HtmlErrorKind::Math(s) => write!(f, "invalid math: {s}"),
+            HtmlErrorKind::NotImplemented(s) => 
+                write!(f, "`{s}`: not implemented"),
+            HtmlErrorKind::CustomComponent{name, msg} =>
+                write!(f, "Custom component `{name}` failed: `{msg}`"),
+            HtmlErrorKind::Syntax(s) =>
+                write!(f, "syntax error: {s}"),
+            HtmlErrorKind::Link(s) =>
+                write!(f, "invalid link: {s}"),
+            HtmlErrorKind::Theme(s) =>
+                write!(f, "unknown theme: `{s}`"),
+            HtmlErrorKind::TooDeep =>
+                write!(f, "the elements are nested too deeply"),
         }
     }
 }
 
+impl core::fmt::Display for HtmlError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl std::error::Error for HtmlError {}
+
+
+
+
+/// the theme used when no theme is provided
+const DEFAULT_THEME: &str = "base16-ocean.light";
+
+/// returns the default syntect theme called `name`,
+/// or an error if there is no such theme
+pub(crate) fn find_theme(name: Option<&str>) -> Result<&'static Theme, HtmlErrorKind> {
+    let name = name.unwrap_or(DEFAULT_THEME);
+    THEME_SET.themes.get(name)
+        .ok_or_else(|| HtmlErrorKind::Theme(name.to_string()))
+}
 
+/// the names of the default syntect themes, sorted,
+/// that can be used as [`MarkdownProps::theme`]
+pub fn available_themes() -> Vec<&'static str> {
+    // the themes are in a BTreeMap, so already sorted
+    THEME_SET.themes.keys().map(|x| x.as_str()).collect()
+}
+
+/// the languages of the code blocks that are highlighted by default,
+/// as the tokens of the code fences like `rs` or `py`, sorted and without duplicates
+pub fn available_languages() -> Vec<&'static str> {
+    let mut languages: Vec<&'static str> = SYNTAX_SET.syntaxes().iter()
+        .flat_map(|s| s.file_extensions.iter().map(|x| x.as_str()))
+        .collect();
+    languages.sort_unstable();
+    languages.dedup();
+    languages
+}
+
+/// the theme used when no theme is provided, or when it is unknown
+fn default_theme() -> &'static Theme {
+    &THEME_SET.themes[DEFAULT_THEME]
+}
+
+/// highlights each line of `content`, written in the language `lang`.
+/// Each line is a html string
+fn highlight_lines(theme: &Theme, syntaxes: &SyntaxSet, content: &str, lang: &str) -> Option<Vec<String>> {
+    let syntax = syntaxes.find_syntax_by_token(lang)?;
+    let background = theme.settings.background.unwrap_or(Color::WHITE);
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    LinesWithEndings::from(content)
+        .map(|line| {
+            let regions = highlighter.highlight_line(line, syntaxes).ok()?;
+            styled_line_to_highlighted_html(&regions, IncludeBackground::IfDifferent(background)).ok()
+        })
+        .collect()
+}
+
+/// joins the html of the lines of a code block.
+/// If `line_numbers` is true, they are shown in a gutter
+/// that is not selected with the code.
+/// The lines inside `highlighted` get the class `highlighted-line`
+fn code_lines_html(lines: &[String], line_numbers: bool, highlighted: &[RangeInclusive<usize>]) -> String {
+    let is_highlighted = |n: usize| highlighted.iter().any(|r| r.contains(&n));
+    if !line_numbers && highlighted.is_empty() {
+        return lines.concat()
+    }
+    if !line_numbers {
+        return lines.iter().enumerate()
+            .map(|(i, line)| match is_highlighted(i + 1) {
+                true => format!("<span class=\"line highlighted-line\">{line}</span>"),
+                false => format!("<span class=\"line\">{line}</span>"),
+            })
+            .collect()
+    }
+    let mut html = String::from("<table class=\"code-lines\"><tbody>");
+    for (i, line) in lines.iter().enumerate() {
+        let class = match is_highlighted(i + 1) {
+            true => "code-line highlighted-line",
+            false => "code-line",
+        };
+        html.push_str(&format!(
+            "<tr><td class=\"line-number\" style=\"user-select:none\">{}</td>\
+             <td class=\"{class}\">{line}</td></tr>",
+            i + 1
+        ));
+    }
+    html.push_str("</tbody></table>");
+    html
+}
 
+/// the html of a code block without highlighting
+fn plain_code_html(content: &str, line_numbers: bool, highlighted: &[RangeInclusive<usize>]) -> String {
+    let lines: Vec<_> = LinesWithEndings::from(content)
+        .map(escape_html)
+        .collect();
+    code_lines_html(&lines, line_numbers, highlighted)
+}
 
-/// `highlight_code(content, ss, ts)` render the content `content`
+/// `highlight_code(theme, syntaxes, content, kind, line_numbers)` render the content `content`
 /// with syntax highlighting
-fn highlight_code(theme_name: Option<&str>, content: &str, kind: &CodeBlockKind) -> Option<String> {
-    let lang = match kind {
-        CodeBlockKind::Fenced(x) => x,
-        CodeBlockKind::Indented => return None
-    };
+fn highlight_code(theme: &Theme, syntaxes: &SyntaxSet, content: &str, kind: &CodeBlockKind, line_numbers: bool)
+    -> Option<String> {
+    let lang = code_language(kind)?;
+    let lines = highlight_lines(theme, syntaxes, content, lang)?;
 
-    let theme_name = theme_name
-        .clone()
-        .unwrap_or("base16-ocean.light");
-    let theme = THEME_SET.themes.get(theme_name)
-        .expect("unknown theme")
-        .clone();
-
-    Some(
-        syntect::html::highlighted_html_for_string(
-            content,
-            &SYNTAX_SET,
-            SYNTAX_SET.find_syntax_by_token(lang)?,
-            &theme
-            ).ok()?
-    )
+    let (mut html, _) = start_highlighted_html_snippet(theme);
+    html.push_str(&code_lines_html(&lines, line_numbers, &code_highlighted_lines(kind)));
+    html.push_str("</pre>\n");
+    Some(html)
 }
 
-/// renders a source code in a code block, with syntax highlighting if possible.
+/// the grammars used to highlight the code.
+/// The default grammars are only loaded if there is no custom set
+fn syntax_set<'a>(props: &MarkdownProps<'a>) -> &'a SyntaxSet {
+    match props.syntax_set {
+        Some(syntaxes) => syntaxes,
+        None => &SYNTAX_SET,
+    }
+}
+
+/// `highlight_inline_code(theme, syntaxes, content, lang)` renders the inline code `content`
+/// with syntax highlighting, inside a `code` element with the background of the theme
+fn highlight_inline_code(theme: &Theme, syntaxes: &SyntaxSet, content: &str, lang: &str) -> Option<String> {
+    let lines = highlight_lines(theme, syntaxes, content, lang)?;
+    let Color { r, g, b, .. } = theme.settings.background.unwrap_or(Color::WHITE);
+    Some(format!("<code style=\"background-color:#{r:02x}{g:02x}{b:02x};\">{}</code>", lines.concat()))
+}
+
+/// the language of a code block, given by the first word of its info string,
+/// unless it is an option like `title="main.rs"`
+fn code_language(kind: &CodeBlockKind) -> Option<&str> {
+    match kind {
+        CodeBlockKind::Fenced(info) => info
+            .split(|c: char| c.is_whitespace() || c == '{')
+            .next()
+            .filter(|l| !l.is_empty() && !l.contains('=')),
+        CodeBlockKind::Indented => None
+    }
+}
+
+/// the lines to highlight in a code block, like `{1,4-6}` in ```` ```rust {1,4-6} ````
+fn code_highlighted_lines(kind: &CodeBlockKind) -> Vec<RangeInclusive<usize>> {
+    match kind {
+        CodeBlockKind::Fenced(info) => highlighted_lines(info),
+        CodeBlockKind::Indented => Vec::new()
+    }
+}
+
+/// renders a source code in a code block, with syntax highlighting if possible,
+/// a header with its title if its info string has one, like `title="main.rs"`,
+/// and a button to copy it if the props have `code_copy_button`.
+/// The code blocks of some languages are rendered by the context instead,
+/// see [`Context::render_fenced_block`].
 /// `cx`: the current markdown context
+/// `theme`: the theme used to highlight the code
+/// `dark_theme`: the theme used in dark mode, if any, see [`MarkdownProps::dark_theme`]
 /// `source`: the source to render 
 /// `range`: the position of the code in the original source
 fn render_code_block<'a, 'callback, F: Context<'a, 'callback>>(
     cx: F,
+    theme: &Theme,
+    dark_theme: Option<&Theme>,
     source: String,
     k: &CodeBlockKind,
     range: Range<usize>
     ) -> F::View {
 
+    if let Some(view) = code_language(k).and_then(|lang| cx.render_fenced_block(lang, &source, range.clone())) {
+        return view
+    }
+
+    let copy_handler = match cx.props().code_copy_button {
+        true => cx.make_copy_handler(source.clone()),
+        false => None,
+    };
+    let title = match k {
+        CodeBlockKind::Fenced(info) => code_block_title(info),
+        CodeBlockKind::Indented => None,
+    };
+    let code = match dark_theme {
+        Some(dark_theme) => {
+            let themed = |class: &str, view| cx.el_with_attributes(Div, view, ElementAttributes {
+                classes: vec![class.to_string()],
+                ..Default::default()
+            });
+            let light = code_block_view(cx, theme, cx.props().theme, source.clone(), k, range.clone());
+            let dark = code_block_view(cx, dark_theme, cx.props().dark_theme, source, k, range);
+            cx.el_fragment(vec![themed("light-theme", light), themed("dark-theme", dark)])
+        },
+        None => code_block_view(cx, theme, cx.props().theme, source, k, range),
+    };
+
+    let mut children = Vec::new();
+    if let Some(title) = title {
+        let header_attributes = ElementAttributes {
+            classes: vec!["code-title".to_string()],
+            ..Default::default()
+        };
+        children.push(cx.el_with_attributes(Div, cx.el_text(title.into()), header_attributes));
+    }
+    children.push(code);
+
+    let mut attributes = ElementAttributes {
+        classes: vec!["code-block".to_string()],
+        ..Default::default()
+    };
+    if let Some(handler) = copy_handler {
+        let button_attributes = ElementAttributes {
+            classes: vec!["copy-button".to_string()],
+            style: Some("position: absolute; top: 0; right: 0".to_string()),
+            on_click: Some(handler),
+            ..Default::default()
+        };
+        children.push(cx.el_with_attributes(Button, cx.el_text("copy".into()), button_attributes));
+        attributes.style = Some("position: relative".to_string());
+    }
+
+    match children.len() {
+        1 => children.remove(0),
+        _ => cx.el_with_attributes(Div, cx.el_fragment(children), attributes),
+    }
+}
+
+/// renders a source code in a code block, with syntax highlighting if possible.
+/// `theme_name` is the name of `theme`, to find the code in the cache
+fn code_block_view<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    theme: &Theme,
+    theme_name: Option<&str>,
+    source: String,
+    k: &CodeBlockKind,
+    range: Range<usize>
+    ) -> F::View {
+
+    let source = match cx.props().code_tab_width {
+        Some(width) => expand_leading_tabs(&source, width),
+        None => source,
+    };
+
     let code_attributes = ElementAttributes{
-        on_click: Some(cx.make_md_handler(range, true)),
+        on_click: Some(cx.make_element_handler(&Pre, range, true)),
         ..Default::default()
     };
 
-    match highlight_code(cx.props().theme, &source, &k) {
-        None => cx.el_with_attributes(
+    let line_numbers = cx.props().code_line_numbers;
+
+    if cx.props().highlight_mode == HighlightMode::ClientSide {
+        let language_attributes = ElementAttributes {
+            classes: code_language(k)
+                .map(|l| vec![format!("language-{l}")])
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let highlighted = code_highlighted_lines(k);
+        let code = match line_numbers || !highlighted.is_empty() {
+            true => cx.el_span_with_inner_html(
+                plain_code_html(&source, line_numbers, &highlighted),
+                Default::default()
+            ),
+            false => cx.el_text(source.into()),
+        };
+        return cx.el_with_class_map(
+            Pre,
+            cx.el_with_class_map(Code, code, language_attributes),
+            code_attributes
+        )
+    }
+
+    let syntaxes = syntax_set(&cx.props());
+    // a theme given by value has no name to find its code in the cache
+    let by_value = cx.props().theme_value.is_some_and(|t| core::ptr::eq(t, theme));
+    let highlighted = match cx.props().cache.filter(|_| !by_value) {
+        Some(cache) => {
+            let key = CodeKey {
+                theme: theme_name.map(|x| x.to_string()),
+                info: match k {
+                    CodeBlockKind::Fenced(info) => info.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                },
+                line_numbers,
+                source: source.clone(),
+            };
+            cache.highlighted_code(key, || highlight_code(theme, syntaxes, &source, &k, line_numbers))
+        },
+        None => highlight_code(theme, syntaxes, &source, &k, line_numbers),
+    };
+
+    match highlighted {
+        None if line_numbers || !code_highlighted_lines(k).is_empty() => cx.el_with_class_map(
+            Pre,
+            cx.el_span_with_inner_html(
+                plain_code_html(&source, line_numbers, &code_highlighted_lines(k)),
+                Default::default()
+            ),
+            code_attributes
+        ),
+        None => cx.el_with_class_map(
             Code,
             cx.el(Code, cx.el_text(source.into())),
             code_attributes
@@ -123,22 +403,71 @@ fn render_code_block<'a, 'callback, F: Context<'a, 'callback>>(
     }
 }
 
-#[cfg(features="maths")]
+#[cfg(feature="maths")]
+/// the katex options to render inline or display maths,
+/// with the latex macros `macros`, like `\RR`.
+/// `trust`: allow commands like `\href` or `\includegraphics`
+/// `output`: the markup of the formulas, html and/or mathml
+pub(crate) fn math_opts(
+    display: bool,
+    macros: Option<&std::collections::BTreeMap<String, String>>,
+    trust: bool,
+    output: MathOutput,
+    ) -> katex::Opts {
+    let output_type = match output {
+        MathOutput::HtmlOnly => katex::OutputType::Html,
+        MathOutput::MathMlOnly => katex::OutputType::Mathml,
+        MathOutput::HtmlAndMathMl => katex::OutputType::HtmlAndMathml,
+    };
+    let mut builder = katex::Opts::builder();
+    builder.display_mode(display).trust(trust).output_type(output_type);
+    for (name, expansion) in macros.into_iter().flatten() {
+        builder.add_macro(name.clone(), expansion.clone());
+    }
+    builder.build().unwrap()
+}
+
+#[cfg(feature="maths")]
+lazy_static::lazy_static!{
+    static ref INLINE_MATH_OPTS: katex::Opts = math_opts(false, None, false, MathOutput::default());
+    static ref DISPLAY_MATH_OPTS: katex::Opts = math_opts(true, None, false, MathOutput::default());
+}
+
+#[cfg(feature="maths")]
+/// the default katex options, built once and shared by every formula
+fn default_math_opts(display: bool) -> &'static katex::Opts {
+    if display {
+        &DISPLAY_MATH_OPTS
+    }
+    else {
+        &INLINE_MATH_OPTS
+    }
+}
+
+#[cfg(feature="maths")]
 /// `render_maths(content)` returns a html node
 /// with the latex content `content` compiled inside
-fn render_maths<'a, 'callback, F: Context<'a, 'callback>>(cx: F, content: &str, display_mode: &MathMode, range: Range<usize>) 
-    -> Result<F::View, HtmlError>{
-    let opts = katex::Opts::builder()
-        .display_mode(*display_mode == MathMode::Display)
-        .build()
-        .unwrap();
+fn render_maths<'a, 'callback, F: Context<'a, 'callback>>(cx: F, source: &str, content: &str, display_mode: &MathMode, range: Range<usize>) 
+    -> Result<F::View, HtmlErrorKind>{
+    let display = *display_mode == MathMode::Display;
+    let props = cx.props();
+    let custom_opts;
+    let default_output = props.math_output == MathOutput::default();
+    let opts = match (props.math_macros, props.math_trust, default_output, props.cache) {
+        (None, false, true, Some(cache)) => cache.math_opts(display),
+        (None, false, true, None) => default_math_opts(display),
+        (macros, trust, _, _) => {
+            custom_opts = math_opts(display, macros, trust, props.math_output);
+            &custom_opts
+        }
+    };
 
     let class_name = match display_mode {
         MathMode::Inline => "math-inline",
         MathMode::Display => "math-flow",
     };
 
-    let callback = cx.make_md_handler(range, true);
+    let callback = cx.make_element_handler(&HtmlElement::Math, range.clone(), true);
 
     let attributes = ElementAttributes{
             classes: vec![class_name.to_string()],
@@ -148,7 +477,38 @@ fn render_maths<'a, 'callback, F: Context<'a, 'callback>>(cx: F, content: &str,
 
     match katex::render_with_opts(content, opts){
         Ok(x) => Ok(cx.el_span_with_inner_html(x, attributes)),
-        Err(_) => Err(HtmlError::Math),
+        Err(_) => Err(HtmlErrorKind::Math(math_source(source, content, display_mode, range))),
+    }
+}
+
+#[cfg(all(feature="client-maths", not(feature="maths")))]
+/// renders the latex `content` as text, between the delimiters `\(` and `\)`,
+/// or `\[` and `\]` for display maths,
+/// for the auto-render script of katex or mathjax
+fn render_client_maths<'a, 'callback, F: Context<'a, 'callback>>(cx: F, content: &str, display_mode: &MathMode, range: Range<usize>)
+    -> F::View {
+    let (class_name, text) = match display_mode {
+        MathMode::Inline => ("math-inline", format!("\\({content}\\)")),
+        MathMode::Display => ("math-flow", format!("\\[{content}\\]")),
+    };
+    let attributes = ElementAttributes {
+        classes: vec!["math".to_string(), class_name.to_string()],
+        on_click: Some(cx.make_element_handler(&HtmlElement::Math, range, true)),
+        ..Default::default()
+    };
+    cx.el_with_attributes(Span, cx.el_text(text.into()), attributes)
+}
+
+#[cfg(feature="maths")]
+/// the markdown source of a formula, with its delimiters.
+/// If `range` doesn't point to the formula, the delimiters are reconstructed
+fn math_source(source: &str, content: &str, display_mode: &MathMode, range: Range<usize>) -> String {
+    match source.get(range) {
+        Some(s) if s.contains(content) => s.to_string(),
+        _ => match display_mode {
+            MathMode::Inline => format!("${content}$"),
+            MathMode::Display => format!("$${content}$$"),
+        }
     }
 }
 
@@ -186,21 +546,88 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     cell_index: usize,
     /// the root tag that this renderer is rendering
     end_tag: Option<TagEnd>,
-    /// the current component we are inside of.
-    /// custom components doesn't allow nesting.
-    current_component: Option<String>
+    /// the name of the inline html tag that closes this renderer, if any
+    end_html_tag: Option<String>,
+    /// true once the closing event of `end_tag` was consumed,
+    /// either by this renderer or by a nested one
+    done: bool,
+    /// the markdown source, that the ranges of the events refer to
+    source: &'a str,
+    /// wether the rendered elements are inside inline content, like a paragraph
+    inline: bool,
+    /// the number of renderers this renderer is nested in
+    depth: usize,
+    /// the innermost block being rendered, like a paragraph or a heading,
+    /// that a click on its text reports
+    block: HtmlElement,
+}
+
+/// returns the element to render for the html tag `name`,
+/// if it is allowed by `safe_tags`
+fn safe_element(safe_tags: &HashSet<&str>, name: &str) -> Option<HtmlElement> {
+    if safe_tags.contains(name) {
+        HtmlElement::from_safe_tag(name)
+    }
+    else {
+        None
+    }
+}
+
+/// the attributes of an allowed html element: only its classes are kept
+fn safe_attributes<H>(call: &ComponentCall) -> ElementAttributes<H> {
+    ElementAttributes {
+        classes: call.attributes.get("class")
+            .map(|c| c.split_whitespace().map(|x| x.to_string()).collect())
+            .unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// returns true if `name` is an html element that cannot have children,
+/// like `br` or `img`
+fn is_void_element(name: &str) -> bool {
+    matches!(name, "br" | "hr" | "img" | "input" | "wbr" | "meta" | "link" | "source")
+}
+
+/// returns true if the content of `tag` is inline content, like text or links.
+/// Items of tight lists directly contain inline content.
+fn is_inline_container(tag: &Tag) -> bool {
+    matches!(tag,
+        Tag::Paragraph | Tag::Heading{..} | Tag::TableCell | Tag::Item
+        | Tag::Emphasis | Tag::Strong | Tag::Strikethrough
+        | Tag::Link{..} | Tag::Image{..}
+    )
+}
+
+/// the title of a github alert of the kind `kind`, like `Note`
+fn admonition_title(kind: &str) -> String {
+    let mut title = kind.to_string();
+    title[..1].make_ascii_uppercase();
+    title
 }
 
 /// returns true if `raw_html`:
 /// - starts with '<'
 /// - ends with '>'
-/// - does not have any '<' or '>' in between
+/// - does not have any '<' or '>' in between,
+///     except inside a quoted attribute value
 fn can_be_custom_component(raw_html: &str) -> bool {
-    let chars: Vec<_> = raw_html.trim().chars().collect();
-    let len = chars.len();
-    if len==0 {return false};
-    let (fst, middle, last) = (chars[0], &chars[1..len-1], chars[len-1]);
-    fst == '<' && last == '>' && middle.into_iter().all(|c| c!=&'<' && c!=&'>')
+    let raw_html = raw_html.trim();
+    let inner = match raw_html.strip_prefix('<').and_then(|x| x.strip_suffix('>')) {
+        Some(inner) => inner,
+        None => return false,
+    };
+    // same quoting rules as `CustomHtmlTag::from_str`:
+    // attribute values are wrapped in `"`
+    let mut in_quotes = false;
+    for c in inner.chars() {
+        match (in_quotes, c) {
+            (_, '"') => in_quotes = !in_quotes,
+            (false, '<') | (false, '>') => return false,
+            _ => ()
+        }
+    }
+    !in_quotes
 }
 
 impl<'a, 'callback, 'c, I, F> Iterator for Renderer<'a, 'callback, 'c, I, F> 
@@ -212,58 +639,69 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
 
     fn next(&mut self) -> Option<Self::Item> {
         use Event::*;
+        if self.done {
+            return None
+        }
         let (item, range): (Event<'a>, Range<usize>) = self.stream.next()? ;
-        let range = range.clone();
+        let error_range = range.clone();
 
         let cx = self.cx;
 
         let rendered = match item {
-            Start(t) => self.render_tag(t, range),
+            Start(t) if self.depth >= cx.props().max_depth => {
+                // the element is skipped, instead of overflowing the stack
+                collect_events(self.stream, as_closing_tag(&t));
+                Err(HtmlErrorKind::TooDeep)
+            },
+            Start(t) => {
+                let block = self.block_element(&t).unwrap_or_else(|| self.block.clone());
+                let parent_block = std::mem::replace(&mut self.block, block);
+                let view = self.render_tag(t, range);
+                self.block = parent_block;
+                view
+            },
             End(end) => {
                 // check if the closing tag is the tag that was open
                 // when this renderer was created
                 match self.end_tag {
-                    Some(t) if t == end => return None,
+                    Some(t) if t == end => {
+                        self.done = true;
+                        return None
+                    },
                     Some(t) => panic!("{t:?} is a wrong closing tag"),
                     None => panic!("didn't expect a closing tag")
                 }
             },
-            Text(s) => Ok(cx.render_text(s, range)),
+            // the text keeps its range, so clicks still point to the shortcodes
+            Text(s) if cx.props().emoji => match expand_shortcodes(&s) {
+                Some(expanded) => Ok(cx.render_text(expanded.into(), range, &self.block)),
+                None => Ok(cx.render_text(s, range, &self.block)),
+            },
+            Text(s) => Ok(cx.render_text(s, range, &self.block)),
+            Code(s) if cx.props().inline_code_highlight => Ok(self.inline_code(s, range)),
             Code(s) => Ok(cx.render_code(s, range)),
             InlineHtml(s) => {
-                let attributes = ElementAttributes {
-                    on_click: Some(self.cx.make_md_handler(range, false)),
-                    ..ElementAttributes::default()
-                };
-                Ok(self.cx.el_span_with_inner_html(s.to_string(), attributes))
+                if self.is_closing_html_tag(&s) {
+                    return None
+                }
+                self.inline_html(&s, range)
             },
             Html(_) => panic!("html outside html block"), 
-            FootnoteReference(_) => Err(HtmlError::not_implemented("footnotes refs")),
+            FootnoteReference(_) => self.unsupported("footnotes refs", range),
             SoftBreak => Ok(self.next()?),
             HardBreak => Ok(self.cx.el_br()),
             Rule => Ok(cx.render_rule(range)),
             TaskListMarker(m) => Ok(cx.render_tasklist_marker(m, range)),
-            #[cfg(features="maths")]
-            Math(disp, content) => render_maths(self.cx, &content, &disp, range),
-            #[cfg(not(features="maths"))]
-            Math(_, _) => Err(HtmlError::Math)
+            #[cfg(feature="maths")]
+            Event::Math(disp, content) => render_maths(self.cx, self.source, &content, &disp, range),
+            #[cfg(all(feature="client-maths", not(feature="maths")))]
+            Event::Math(disp, content) => Ok(render_client_maths(self.cx, &content, &disp, range)),
+            // without katex, formulas are shown as an inline error
+            #[cfg(not(any(feature="maths", feature="client-maths")))]
+            Event::Math(_, _) => Err(HtmlErrorKind::not_implemented("maths, without the `maths` feature"))
         };
 
-        Some(
-            rendered.unwrap_or_else(|e| self.cx.el_with_attributes(
-                    Span,
-                    self.cx.el_fragment(vec![
-                        self.cx.el_text(e.to_string().into()),
-                        self.cx.el_br(),
-                    ]),
-                    ElementAttributes {
-                        classes: vec!["markdown-error".to_string()],
-                        on_click: None,
-                        ..Default::default()
-                    }
-                )
-            )
-        )
+        Some(rendered.unwrap_or_else(|e| self.error_view(e, error_range)))
     }
 }
 
@@ -274,114 +712,527 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
 {
     /// creates a new renderer from a stream of events.
     /// It returns an iterator of [`F::View`]
-    pub fn new(cx: F, events: &'c mut I)-> Self 
+    pub fn new(cx: F, source: &'a str, events: &'c mut I)-> Self 
     {
 
         Self {
             __marker: PhantomData,
             cx,
+            source,
             stream: events,
             column_alignment: None,
             cell_index: 0,
             end_tag: None,
-            current_component: None,
+            end_html_tag: None,
+            done: false,
+            inline: false,
+            depth: 0,
+            block: Div,
         }
     }
 
+    /// the block element of `tag`, like a heading, if it is a block with text inside.
+    /// The text of the other tags, like emphasis, reports the block around them
+    fn block_element(&self, tag: &Tag) -> Option<HtmlElement> {
+        Some(match tag {
+            Tag::Paragraph => Paragraph,
+            Tag::Heading{level, ..} => HtmlElement::heading(offset_heading_level(*level, self.cx.props().heading_offset)),
+            Tag::BlockQuote => BlockQuote,
+            Tag::Item => Li,
+            Tag::TableCell => Tcell,
+            _ => return None
+        })
+    }
+
+    /// renders an element that is not supported yet, like footnotes,
+    /// as configured by [`crate::MarkdownProps::unsupported`].
+    /// `range` is the position of the whole element in the source
+    fn unsupported(&self, name: &str, range: Range<usize>) -> Result<F::View, HtmlErrorKind> {
+        match self.cx.props().unsupported {
+            Unsupported::Skip => Ok(self.cx.el_empty()),
+            Unsupported::Error => Err(HtmlErrorKind::not_implemented(name)),
+            Unsupported::RawText => {
+                let raw = self.source.get(range).unwrap_or_default();
+                Ok(self.cx.el_text(raw.into()))
+            }
+        }
+    }
+
+    /// the theme called `name` used to highlight the code blocks,
+    /// from the custom themes or the default ones.
+    /// An unknown theme is an error
+    fn code_theme(&self, name: Option<&str>) -> Result<&'a Theme, HtmlErrorKind> {
+        let props = self.cx.props();
+        if props.highlight_mode == HighlightMode::ClientSide {
+            return Ok(default_theme())
+        }
+        let custom_theme = props.themes.zip(name)
+            .and_then(|(themes, name)| themes.get(name));
+        if let Some(theme) = custom_theme {
+            return Ok(theme)
+        }
+        match props.cache {
+            Some(cache) => cache.theme(name),
+            None => find_theme(name),
+        }
+    }
+
+    /// the theme used to highlight the code, and the code blocks in light mode:
+    /// [`MarkdownProps::theme_value`] if it is set, or the theme called [`MarkdownProps::theme`]
+    fn light_theme(&self) -> Result<&'a Theme, HtmlErrorKind> {
+        match self.cx.props().theme_value {
+            Some(theme) => Ok(theme),
+            None => self.code_theme(self.cx.props().theme),
+        }
+    }
+
+    /// renders inline code with syntax highlighting if it starts with its language,
+    /// like `rust:let x = 1`, see [`MarkdownProps::inline_code_highlight`].
+    /// Otherwise, or if the language is unknown, it is rendered as usual
+    fn inline_code(&self, code: CowStr<'a>, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let (lang, content) = match inline_code_language(&code) {
+            Some(x) => x,
+            None => return cx.render_code(code, range)
+        };
+        let attributes = ElementAttributes {
+            on_click: Some(cx.make_element_handler(&Code, range.clone(), false)),
+            ..Default::default()
+        };
+        if cx.props().highlight_mode == HighlightMode::ClientSide {
+            let attributes = ElementAttributes {
+                classes: vec![format!("language-{lang}")],
+                ..attributes
+            };
+            return cx.el_with_class_map(Code, cx.el_text(content.to_string().into()), attributes)
+        }
+        let highlighted = self.light_theme().ok()
+            .and_then(|theme| highlight_inline_code(theme, syntax_set(&cx.props()), content, lang));
+        match highlighted {
+            Some(html) => cx.el_span_with_inner_html(html, attributes),
+            None => cx.render_code(code, range)
+        }
+    }
+
+    /// renders an error inside the markdown, unless the context
+    /// renders it itself, see [`Context::on_error`].
+    /// `range` is the position of the element in the source.
+    /// Inside inline content, it is a `span` that doesn't break the line,
+    /// otherwise it is a block.
+    /// An invalid formula is shown as its source.
+    fn error_view(&self, kind: HtmlErrorKind, range: Range<usize>) -> F::View {
+        let e = HtmlError { kind, range };
+        if let Some(view) = self.cx.on_error(&e) {
+            return view
+        }
+        // keep the formula readable
+        if let HtmlErrorKind::Math(raw) = &e.kind {
+            let attributes = ElementAttributes {
+                classes: vec!["error-math".to_string()],
+                ..Default::default()
+            };
+            return self.cx.el_with_attributes(Span, self.cx.el_text(raw.clone().into()), attributes)
+        }
+        let element = if self.inline {Span} else {Div};
+        // a click on the error jumps to the element in the source,
+        // and reports the block that contains it
+        let attributes = ElementAttributes {
+            classes: vec!["markdown-error".to_string()],
+            on_click: Some(self.cx.make_element_handler(&self.block, e.range.clone(), true)),
+            ..Default::default()
+        };
+        let message = self.cx.el_text(e.to_string().into());
+        self.cx.el_with_attributes(element, message, attributes)
+    }
+
+    /// parses `raw_html` as a sequence of custom components without children,
+    /// like `<X/><Y/>` or `<X><X>`, with the position of each one in the source.
+    /// `range` is the position of `raw_html`.
+    /// Returns None if one of them is not registered.
+    fn registered_components(&self, raw_html: &str, range: Range<usize>)
+        -> Option<Vec<(ComponentCall, Range<usize>)>> {
+        let calls = parse_component_sequence(raw_html)?;
+        if calls.iter().all(|(c, _)| self.cx.has_custom_component(&c.name)) {
+            Some(calls.into_iter()
+                 .map(|(c, r)| (c, self.tag_range(raw_html, range.clone(), r)))
+                 .collect())
+        }
+        else {
+            None
+        }
+    }
+
+    /// the position in the source of the tag at `tag` inside `raw_html`,
+    /// an html event at `range`.
+    /// If the html of the event differs from the source, like inside block quotes,
+    /// returns the position of the whole event
+    fn tag_range(&self, raw_html: &str, range: Range<usize>, tag: Range<usize>) -> Range<usize> {
+        match self.source.get(range.clone()) {
+            Some(html) if html == raw_html => range.start+tag.start..range.start+tag.end,
+            _ => range
+        }
+    }
+
+    /// renders a sequence of custom components without children
+    fn component_sequence(&mut self, calls: Vec<(ComponentCall, Range<usize>)>) -> F::View {
+        let mut views = Vec::new();
+        for (call, range) in calls {
+            views.push(self.custom_component_inline(call, range));
+        }
+        self.cx.el_fragment(views)
+    }
+
+    /// returns true if `raw_html` is the closing tag
+    /// of the inline html element this renderer is rendering
+    fn is_closing_html_tag(&self, raw_html: &str) -> bool {
+        match (&self.end_html_tag, raw_html.parse::<CustomHtmlTag>()) {
+            (Some(end), Ok(CustomHtmlTag::End(name))) => end == &name,
+            _ => false
+        }
+    }
+
+    /// renders inline html.
+    /// Custom components are rendered without children,
+    /// even with a start tag like `<X>`, since they can't have children inside a paragraph.
+    /// Otherwise, if `safe_tags` are provided, only the allowed tags are rendered
+    /// and every other tag is stripped.
+    /// Otherwise, the html is given to the context as is.
+    fn inline_html(&mut self, raw_html: &str, range: Range<usize>)
+        -> Result<F::View, HtmlErrorKind> {
+        if let Ok(CustomHtmlTag::Inline(call) | CustomHtmlTag::Start(call)) = raw_html.parse() {
+            if self.cx.has_custom_component(&call.name) {
+                return Ok(self.custom_component_inline(call, range))
+            }
+        }
+
+        let safe_tags = match self.cx.props().safe_tags {
+            Some(tags) => tags,
+            None => {
+                let attributes = ElementAttributes {
+                    on_click: Some(self.cx.make_element_handler(&Span, range, false)),
+                    ..ElementAttributes::default()
+                };
+                return Ok(self.raw_html(raw_html, attributes))
+            }
+        };
+
+        Ok(match raw_html.parse::<CustomHtmlTag>() {
+            // the content of the element is rendered without it
+            Ok(CustomHtmlTag::Start(_)) if self.depth >= self.cx.props().max_depth
+                => return Err(HtmlErrorKind::TooDeep),
+            Ok(CustomHtmlTag::Start(call)) => match safe_element(safe_tags, &call.name) {
+                Some(e) => self.safe_html_element(e, call),
+                None => self.cx.el_empty(),
+            },
+            Ok(CustomHtmlTag::Inline(call)) => match safe_element(safe_tags, &call.name) {
+                Some(e) => self.cx.el_with_class_map(e, self.cx.el_empty(), safe_attributes(&call)),
+                None => self.cx.el_empty(),
+            },
+            Ok(CustomHtmlTag::End(_)) | Err(_) => self.cx.el_empty()
+        })
+    }
+
+    /// renders an allowed inline html element,
+    /// with the markdown until its closing tag inside
+    fn safe_html_element(&mut self, element: HtmlElement, call: ComponentCall) -> F::View {
+        let attributes = safe_attributes(&call);
+        let mut sub_renderer = Renderer {
+            __marker: PhantomData,
+            cx: self.cx,
+            stream: self.stream,
+            column_alignment: self.column_alignment.clone(),
+            cell_index: 0,
+            end_tag: self.end_tag,
+            end_html_tag: Some(call.name),
+            done: false,
+            source: self.source,
+            inline: true,
+            depth: self.depth + 1,
+            block: self.block.clone(),
+        };
+        let children: Vec<_> = (&mut sub_renderer).collect();
+        // the tag was not closed before the end of the parent element
+        if sub_renderer.done {
+            self.done = true;
+        }
+        self.cx.el_with_class_map(element, self.cx.el_fragment(children), attributes)
+    }
+
+    /// renders a block of raw html, keeping only the tags allowed by `safe_tags`.
+    /// The text between the tags is rendered as markdown.
+    fn safe_html_block(&self, raw_html: &'a str, offset: usize, safe_tags: &HashSet<&str>)
+        -> F::View {
+        let mut tokens = tokenize_html(raw_html).into_iter();
+        let children = self.safe_html_children(raw_html, offset, &mut tokens, None, safe_tags);
+        self.cx.el_fragment(children)
+    }
+
+    /// renders the html `tokens` of `raw_html` until the closing tag `end`.
+    /// `offset` is the position of `raw_html` inside the markdown source.
+    fn safe_html_children(&self, raw_html: &'a str, offset: usize,
+                          tokens: &mut impl Iterator<Item=HtmlToken>,
+                          end: Option<&str>, safe_tags: &HashSet<&str>) -> Vec<F::View> {
+        let mut children = Vec::new();
+        while let Some(token) = tokens.next() {
+            match token {
+                HtmlToken::Text(r) => {
+                    let text = &raw_html[r.clone()];
+                    if text.trim().is_empty() {
+                        continue
+                    }
+                    let start = offset + r.start;
+                    let events = crate::parse(text, &self.cx.props())
+                        .into_iter()
+                        .map(|(e, r)| (e, r.start+start..r.end+start))
+                        .collect();
+                    children.push(self.render_events(events, false));
+                },
+                HtmlToken::Tag(r) => match raw_html[r].parse::<CustomHtmlTag>() {
+                    Ok(CustomHtmlTag::Start(call)) if !is_void_element(&call.name) => {
+                        let inner = self.safe_html_children(raw_html, offset, tokens,
+                                                            Some(call.name.as_str()), safe_tags);
+                        let inner = self.cx.el_fragment(inner);
+                        children.push(match safe_element(safe_tags, &call.name) {
+                            Some(e) => self.cx.el_with_class_map(e, inner, safe_attributes(&call)),
+                            // the tag is stripped, but not its content
+                            None => inner,
+                        });
+                    },
+                    Ok(CustomHtmlTag::Start(call)) | Ok(CustomHtmlTag::Inline(call)) => {
+                        if let Some(e) = safe_element(safe_tags, &call.name) {
+                            children.push(
+                                self.cx.el_with_class_map(e, self.cx.el_empty(), safe_attributes(&call))
+                            )
+                        }
+                    },
+                    Ok(CustomHtmlTag::End(name)) if Some(name.as_str()) == end => break,
+                    // stray closing tags and comments are ignored
+                    _ => ()
+                }
+            }
+        }
+        children
+    }
+
     /// try to render `raw_html` as a custom component.
     /// - if it looks like `<Component/>` and Component is registered,
     ///     it will render the corresponding component
     /// - it it looks like `<Component>`, and Component is registered, 
-    /// it will extract markdown until it finds `<Component/>`
-    /// In any other cases, it will render the strinng as raw html.
-    fn html(&mut self, raw_html: &str, _range: Range<usize>) 
-        -> Result<F::View, HtmlError> {
-
-            // TODO: refactor
-
-            match &self.current_component {
-                Some(current_name) => {
-                    if self.end_tag.is_some() {
-                        return Err(HtmlError::component(raw_html, 
-                                                        "please make sure there is a newline before the end of your component"))
-                    }
-                    match raw_html.parse() {
-                        Ok(CustomHtmlTag::End(name)) if &name==current_name => {
-                            Ok(self.next().unwrap_or(self.cx.el_empty()))
-                        },
-                        Ok(_) => Err(HtmlError::component(current_name, 
-                                                          "expected end of component")),
-                        Err(e) => Err(HtmlError::syntax(e))
-                    }
+    /// it will extract markdown until it finds `</Component>`.
+    /// If there is no `</Component>`, it renders an error at the start tag,
+    /// followed by the markdown after it.
+    /// Only registered or capitalised names are components, see [`Self::is_component_tag`].
+    /// In any other cases, it will render the strinng as raw html, inside a block.
+    fn html(&mut self, raw_html: &str, range: Range<usize>) 
+        -> Result<F::View, HtmlErrorKind> {
+            if can_be_custom_component(raw_html) && self.is_component_tag(raw_html) {
+                let tag = raw_html.len()-raw_html.trim_start().len()..raw_html.trim_end().len();
+                let tag_range = self.tag_range(raw_html, range, tag);
+                match raw_html.parse() {
+                    Ok(CustomHtmlTag::Inline(s) | CustomHtmlTag::Start(s))
+                        if !self.cx.has_custom_component(&s.name)
+                        => self.unknown_component(&s.name, raw_html, false),
+                    Ok(CustomHtmlTag::End(name)) if !self.cx.has_custom_component(&name)
+                        => self.unknown_component(&name, raw_html, true),
+                    Ok(CustomHtmlTag::Inline(s)) => {
+                        let view = self.custom_component_inline(s, tag_range);
+                        Ok(self.wrap_block_component(view))
+                    },
+                    Ok(CustomHtmlTag::End(name)) => Err(
+                        HtmlErrorKind::component(name, "expected start, not end")),
+                    Ok(CustomHtmlTag::Start(s)) => Ok(self.custom_component(s, tag_range)),
+                    Err(e) => Err(HtmlErrorKind::syntax(e))
                 }
-                None => {
-                    if can_be_custom_component(raw_html) {
-                        match raw_html.parse() {
-                            Ok(CustomHtmlTag::Inline(s)) => self.custom_component_inline(s),
-                            Ok(CustomHtmlTag::End(name)) => Err(
-                                HtmlError::component(name, "expected start, not end")),
-                            Ok(CustomHtmlTag::Start(s)) => self.custom_component(s),
-                            Err(e) => Err(HtmlError::syntax(e))
-                        }
-                    }
-                    else {
-                        Ok(self.cx.el_span_with_inner_html(raw_html.to_string(), 
-                                                           Default::default()))
-                    }
+            }
+            else if let Some(calls) = self.registered_components(raw_html, range.clone()) {
+                let view = self.component_sequence(calls);
+                Ok(self.wrap_block_component(view))
+            }
+            else if let Some(safe_tags) = self.cx.props().safe_tags {
+                // the html from the event can differ from the source
+                // inside containers, like block quotes
+                match self.source.get(range.clone()) {
+                    Some(html) if html.trim_end() == raw_html.trim_end()
+                        => Ok(self.safe_html_block(html, range.start, safe_tags)),
+                    _ => Ok(self.cx.el_text(strip_html_tags(raw_html).into()))
                 }
             }
+            else {
+                let html = self.sanitized_html(raw_html);
+                Ok(self.cx.el_div_with_inner_html(html, Default::default()))
+            }
+        }
+
+    /// puts the components without children `view`, rendered from an html block,
+    /// inside a paragraph if the props ask for it,
+    /// see [`crate::MarkdownProps::wrap_block_components`]
+    fn wrap_block_component(&self, view: F::View) -> F::View {
+        match self.cx.props().wrap_block_components {
+            true => self.cx.el_with_class_map(Paragraph, view, Default::default()),
+            false => view,
+        }
+    }
+
+    /// `raw_html`, sanitized if the props have a [`crate::SanitizeConfig`]
+    fn sanitized_html(&self, raw_html: &str) -> String {
+        match self.cx.props().sanitize {
+            Some(config) => sanitize(raw_html, config),
+            None => raw_html.to_string(),
+        }
+    }
+
+    /// renders `raw_html` as html inside a span, sanitized if the props have a [`crate::SanitizeConfig`]
+    fn raw_html(&self, raw_html: &str, attributes: ElementAttributes<F::Handler<F::MouseEvent>>)
+        -> F::View {
+        self.cx.el_span_with_inner_html(self.sanitized_html(raw_html), attributes)
+    }
+
+    /// wether the tag `raw_html` is meant as a custom component:
+    /// its name is registered by the context, or capitalised like `<Widget/>`.
+    /// The other tags, like `<div class="note">`, are raw html
+    fn is_component_tag(&self, raw_html: &str) -> bool {
+        let name: String = raw_html.trim_start()
+            .trim_start_matches(['<', '/'])
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '/' && *c != '>')
+            .collect();
+        name.starts_with(|c: char| c.is_ascii_uppercase()) || self.cx.has_custom_component(&name)
+    }
+
+    /// renders the tag `raw_html` of the component `name`,
+    /// that is not registered by the context
+    fn unknown_component(&self, name: &str, raw_html: &str, closing: bool)
+        -> Result<F::View, HtmlErrorKind> {
+        match self.cx.props().unknown_component {
+            UnknownComponent::Error if closing
+                => Err(HtmlErrorKind::component(name, "expected start, not end")),
+            UnknownComponent::Error
+                => Err(HtmlErrorKind::component(name, "not a valid component")),
+            UnknownComponent::RawHtml
+                => Ok(self.raw_html(raw_html, Default::default())),
+            UnknownComponent::Placeholder if closing => Ok(self.cx.el_empty()),
+            UnknownComponent::Placeholder => {
+                let attributes = ElementAttributes {
+                    classes: vec!["unknown-component".to_string()],
+                    ..Default::default()
+                };
+                let text = self.cx.el_text(format!("<{name}>").into());
+                Ok(self.cx.el_with_attributes(Span, text, attributes))
+            }
+        }
+    }
+
+    /// extracts the events until the html block `</name>`
+    /// that closes the custom component `name`, see [`component_events`].
+    /// If the parent element ends before, the component ends with it.
+    fn component_events(&mut self, name: &str) -> (Vec<(Event<'a>, Range<usize>)>, ComponentEnd) {
+        let (events, end) = component_events(self.stream, name);
+        if end == ComponentEnd::ParentEnded {
+            self.done = true;
         }
+        (events, end)
+    }
 
-    /// renders a custom component with childrens
-    fn custom_component(&mut self, description: ComponentCall) -> Result<F::View, HtmlError> {
+    /// creates the children of a custom component, that are rendered on demand
+    fn lazy_children(&self, events: Vec<(Event<'a>, Range<usize>)>) -> LazyChildren<'a, F::View> {
+        let cx = self.cx;
+        let source = self.source;
+        let depth = self.depth + 1;
+        let block = self.block.clone();
+        LazyChildren::new(move || {
+            let mut stream = events.clone().into_iter();
+            let sub_renderer = Renderer {
+                depth,
+                block: block.clone(),
+                ..Renderer::new(cx, source, &mut stream)
+            };
+            cx.el_fragment(sub_renderer.collect())
+        })
+    }
+
+    /// puts the rendered component `view` inside an element with the id `id`,
+    /// given by the reserved attribute `id`, like `<Chart id="sales"/>`.
+    /// It is a `span` inside inline content, and a `div` otherwise
+    fn with_component_id(&self, id: Option<String>, view: F::View) -> F::View {
+        let attributes = ElementAttributes {
+            id: match id {
+                Some(id) => Some(id),
+                None => return view
+            },
+            ..Default::default()
+        };
+        match self.inline {
+            true => self.cx.el_with_attributes(Span, view, attributes),
+            false => self.cx.el_with_attributes(Div, view, attributes),
+        }
+    }
+
+    /// renders a custom component with childrens.
+    /// `range` is the position of its start tag,
+    /// also given to the error if the component fails
+    fn custom_component(&mut self, description: ComponentCall, range: Range<usize>) -> F::View {
+        let id = description.attributes.get("id").cloned();
         let name: &str = &description.name;
         if !self.cx.has_custom_component(name){
-            return Err(HtmlError::component(name, "not a valid component"))
+            return self.error_view(HtmlErrorKind::component(name, "not a valid component"), range)
         }
 
-        let sub_renderer = Renderer {
-            __marker: PhantomData,
-            cx: self.cx,
-            stream: self.stream,
-            column_alignment: self.column_alignment.clone(),
-            cell_index: 0,
-            end_tag: self.end_tag,
-            current_component: Some(description.name.clone())
+        let (events, end) = self.component_events(name);
+        if end != ComponentEnd::Closed {
+            // the content is still rendered, to not hide the rest of the document
+            let error = self.error_view(HtmlErrorKind::component(name, "not closed"), range);
+            return self.cx.el_fragment(vec![error, self.render_events(events, false)])
+        }
+        let inner_range = match (events.first(), events.iter().map(|(_, r)| r.end).max()) {
+            (Some((_, first)), Some(end)) => first.start..end,
+            _ => range.end..range.end,
         };
-        let children = self.cx.el_fragment(sub_renderer.collect());
 
-        let props = MdComponentProps {
-            attributes: description.attributes,
-            children
+        let result = if self.cx.has_lazy_children(name) {
+            let props = MdComponentProps {
+                attributes: description.attributes,
+                children: self.lazy_children(events),
+                range: range.clone(),
+                inner_range,
+            };
+            self.cx.render_custom_component_lazy(name, props)
+        }
+        else {
+            let props = MdComponentProps {
+                attributes: description.attributes,
+                children: self.render_events(events, false),
+                range: range.clone(),
+                inner_range,
+            };
+            self.cx.render_custom_component(name, props)
         };
 
-        match self.cx.render_custom_component(name, props) {
-            Ok(x) => Ok(x),
-            Err(e) => Err(HtmlError::CustomComponent {
-                name: name.to_string(),
-                msg: e.0
-            })
+        match result {
+            Ok(x) => self.with_component_id(id, x),
+            Err(e) => self.error_view(HtmlErrorKind::component(name, e.0), range)
         }
     }
 
-    /// renders a custom component without childrens
-    fn custom_component_inline(&mut self, description: ComponentCall) -> Result<F::View, HtmlError> {
+    /// renders a custom component without childrens.
+    /// `range` is the position of its tag,
+    /// also given to the error if the component fails
+    fn custom_component_inline(&mut self, description: ComponentCall, range: Range<usize>) -> F::View {
+        let id = description.attributes.get("id").cloned();
         let name: &str = &description.name;
         if !self.cx.has_custom_component(name){
-            return Err(HtmlError::component(name, "not a valid component"))
+            return self.error_view(HtmlErrorKind::component(name, "not a valid component"), range)
         }
 
         let props = MdComponentProps {
             attributes: description.attributes,
-            children: self.cx.el_empty()
+            children: self.cx.el_empty(),
+            range: range.clone(),
+            inner_range: range.end..range.end,
         };
 
         match self.cx.render_custom_component(name, props) {
-            Ok(x) => Ok(x),
-            Err(e) => Err(HtmlError::CustomComponent {
-                name: name.to_string(),
-                msg: e.0
-            })
+            Ok(x) => self.with_component_id(id, x),
+            Err(e) => self.error_view(HtmlErrorKind::component(name, e.0), range)
         }
     }
 
@@ -395,21 +1246,46 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
             column_alignment: self.column_alignment.clone(),
             cell_index: 0,
             end_tag: Some(as_closing_tag(&tag)),
-            current_component: self.current_component.clone(),
+            end_html_tag: None,
+            done: false,
+            source: self.source,
+            inline: is_inline_container(&tag),
+            depth: self.depth + 1,
+            block: self.block.clone(),
         };
         self.cx.el_fragment(sub_renderer.collect())
     }
 
-    /// extract the text from the next text event
-    fn children_text(&mut self, tag: Tag<'a>) -> Option<String> {
-        let text = match self.stream.next() {
-            Some((Event::Text(s), _)) => Some(s.to_string()),
-            None => None,
-            _ => panic!("expected string event, got something else")
+    /// extracts the events until the end of the tag, without rendering them
+    fn children_events(&mut self, tag: &Tag<'a>) -> Vec<(Event<'a>, Range<usize>)> {
+        collect_events(self.stream, as_closing_tag(tag))
+    }
+
+    /// renders events that were extracted from the stream
+    /// with [`Self::children_events`].
+    /// `inline` tells wether they are inline content, like the content of a link
+    fn render_events(&self, events: Vec<(Event<'a>, Range<usize>)>, inline: bool) -> F::View {
+        let mut stream = events.into_iter();
+        let sub_renderer = Renderer {
+            __marker: PhantomData,
+            cx: self.cx,
+            stream: &mut stream,
+            column_alignment: self.column_alignment.clone(),
+            cell_index: 0,
+            end_tag: None,
+            end_html_tag: None,
+            done: false,
+            inline,
+            source: self.source,
+            depth: self.depth + 1,
+            block: self.block.clone(),
         };
+        self.cx.el_fragment(sub_renderer.collect())
+    }
 
-        self.assert_closing_tag(as_closing_tag(&tag));
-        text
+    /// extract the text until the end of the tag
+    fn children_text(&mut self, tag: Tag<'a>) -> String {
+        collect_text(self.stream, as_closing_tag(&tag))
     }
 
     // check that the closing tag is what was expected
@@ -419,7 +1295,7 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     }
 
     fn render_tag(&mut self, tag: Tag<'a>, range: Range<usize>) 
-    -> Result<F::View, HtmlError> 
+    -> Result<F::View, HtmlErrorKind> 
     {
         let cx = self.cx;
         Ok(match tag.clone() {
@@ -432,58 +1308,161 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
                 self.assert_closing_tag(TagEnd::HtmlBlock);
                 self.html(&raw_html, range)?
             },
-            Tag::Paragraph => cx.el(Paragraph, self.children(tag)),
-            Tag::Heading{level, ..} => cx.el(Heading(level as u8), self.children(tag)),
-            Tag::BlockQuote => cx.el(BlockQuote, self.children(tag)),
-            Tag::CodeBlock(k) => 
-                render_code_block(cx, self.children_text(tag).unwrap_or_default(), &k, range),
-            Tag::List(Some(n0)) => cx.el(Ol(n0 as i32), self.children(tag)),
-            Tag::List(None) => cx.el(Ul, self.children(tag)),
-            Tag::Item => cx.el(Li, self.children(tag)),
+            Tag::Paragraph if cx.props().unwrap_component_paragraphs || cx.props().figure_with_caption => {
+                let events = self.children_events(&tag);
+                let calls = match cx.props().unwrap_component_paragraphs {
+                    true => only_inline_html(&events)
+                        .and_then(|html| self.registered_components(&html, range.clone())),
+                    false => None,
+                };
+                match calls {
+                    Some(calls) => self.component_sequence(calls),
+                    // a figure can't be inside a paragraph
+                    None if cx.props().figure_with_caption && is_captioned_image(&events)
+                        => self.render_events(events, true),
+                    None => cx.el_with_class_map(Paragraph, self.render_events(events, true), Default::default()),
+                }
+            },
+            Tag::Paragraph => cx.el_with_class_map(Paragraph, self.children(tag), Default::default()),
+            Tag::Heading{level, id, ..} => {
+                let attributes = ElementAttributes {
+                    id: id.map(|x| x.to_string()),
+                    ..Default::default()
+                };
+                let level = offset_heading_level(level, cx.props().heading_offset);
+                cx.el_with_class_map(HtmlElement::heading(level), self.children(tag), attributes)
+            },
+            Tag::BlockQuote => {
+                let mut events = self.children_events(&tag);
+                match strip_admonition(&mut events) {
+                    Some(kind) => {
+                        let title_attributes = ElementAttributes {
+                            classes: vec!["admonition-title".to_string()],
+                            ..Default::default()
+                        };
+                        let title = cx.el_with_attributes(
+                            Paragraph,
+                            cx.el_text(admonition_title(kind).into()),
+                            title_attributes
+                        );
+                        let attributes = ElementAttributes {
+                            classes: vec!["admonition".to_string(), format!("admonition-{kind}")],
+                            ..Default::default()
+                        };
+                        let body = self.render_events(events, false);
+                        cx.el_with_class_map(BlockQuote, cx.el_fragment(vec![title, body]), attributes)
+                    },
+                    None => cx.el_with_class_map(BlockQuote, self.render_events(events, false), Default::default()),
+                }
+            },
+            Tag::CodeBlock(k) => {
+                let source = self.children_text(tag);
+                // the code is still shown after an error, with the default theme
+                let mut views = Vec::new();
+                let theme = self.light_theme().unwrap_or_else(|e| {
+                    views.push(self.error_view(e, range.clone()));
+                    default_theme()
+                });
+                let dark_theme = match cx.props().dark_theme {
+                    Some(name) if cx.props().highlight_mode == HighlightMode::Syntect
+                        => match self.code_theme(Some(name)) {
+                            Ok(theme) => Some(theme),
+                            Err(e) => {
+                                views.push(self.error_view(e, range.clone()));
+                                None
+                            }
+                        },
+                    _ => None
+                };
+                views.push(render_code_block(cx, theme, dark_theme, source, &k, range));
+                match views.len() {
+                    1 => views.remove(0),
+                    _ => cx.el_fragment(views),
+                }
+            },
+            Tag::List(Some(n0)) => {
+                let events = self.children_events(&tag);
+                let style = OrderedListStyle {
+                    start: n0 as i32,
+                    reversed: is_reversed_list(self.source, &events),
+                    kind: cx.props().ordered_list_kind,
+                };
+                cx.el_with_class_map(Ol(style), self.render_events(events, false), Default::default())
+            },
+            Tag::List(None) => cx.el_with_class_map(Ul, self.children(tag), Default::default()),
+            Tag::Item => cx.el_with_class_map(Li, self.children(tag), Default::default()),
             Tag::Table(align) => {
                 self.column_alignment = Some(align);
-                cx.el(Table, self.children(tag))
+                let mut head = self.children_events(&tag);
+                let body = split_table_head(&mut head);
+                let mut children = vec![self.render_events(head, false)];
+                if !body.is_empty() {
+                    children.push(cx.el_with_class_map(Tbody, self.render_events(body, false), Default::default()));
+                }
+                cx.el_with_class_map(Table, cx.el_fragment(children), Default::default())
             }
-            Tag::TableHead => cx.el(Thead, self.children(tag)),
-            Tag::TableRow => cx.el(Trow, self.children(tag)),
+            Tag::TableHead => cx.el_with_class_map(Thead, self.children(tag), Default::default()),
+            Tag::TableRow => {
+                self.cell_index = 0;
+                cx.el_with_class_map(Trow, self.children(tag), Default::default())
+            },
             Tag::TableCell => {
-                let align = self.column_alignment.clone().unwrap()[self.cell_index];
+                // a row can have more cells than the head
+                let align = self.column_alignment.as_ref()
+                    .and_then(|a| a.get(self.cell_index).copied())
+                    .unwrap_or(Alignment::None);
                 self.cell_index += 1;
-                cx.el_with_attributes(Tcell, self.children(tag), 
+                cx.el_with_class_map(Tcell, self.children(tag), 
                       ElementAttributes{
                           style:Some(align_string(align).to_string()),
                           ..Default::default()}
                 )
             },
-            Tag::Emphasis => cx.el(Italics, self.children(tag)),
-            Tag::Strong => cx.el(Bold, self.children(tag)),
-            Tag::Strikethrough => cx.el(StrikeThrough, self.children(tag)),
-            Tag::Image{link_type, dest_url, title, ..} => {
+            Tag::Emphasis => cx.el_with_class_map(Italics, self.children(tag), Default::default()),
+            Tag::Strong => cx.el_with_class_map(Bold, self.children(tag), Default::default()),
+            Tag::Strikethrough => cx.el_with_class_map(StrikeThrough, self.children(tag), Default::default()),
+            Tag::Image{link_type, dest_url, title, id} => {
+                let events = self.children_events(&tag);
+                let url = match link_type {
+                    LinkType::Email if !dest_url.starts_with("mailto:") => format!("mailto:{dest_url}"),
+                    _ => dest_url.to_string(),
+                };
                 let description = LinkDescription {
-                    url: dest_url.to_string(),
+                    url,
                     title: title.to_string(),
-                    content: self.children(tag),
+                    id: id.to_string(),
+                    text: plain_text(&events),
+                    content: self.render_events(events, true),
                     link_type,
                     image: true,
+                    wikilink: None,
                 };
-                cx.render_link(description).map_err(HtmlError::Link)?
+                cx.render_link(description).map_err(HtmlErrorKind::Link)?
             },
-            Tag::Link{link_type, dest_url, title, ..} => {
+            Tag::Link{link_type, dest_url, title, id} => {
+                let events = self.children_events(&tag);
+                let url = match link_type {
+                    LinkType::Email if !dest_url.starts_with("mailto:") => format!("mailto:{dest_url}"),
+                    _ => dest_url.to_string(),
+                };
                 let description = LinkDescription {
-                    url: dest_url.to_string(),
+                    url,
                     title: title.to_string(),
-                    content: self.children(tag),
+                    id: id.to_string(),
+                    text: plain_text(&events),
+                    content: self.render_events(events, true),
                     link_type,
                     image: false,
+                    wikilink: self.source.get(range.clone()).and_then(wikilink_page),
                 };
-                cx.render_link(description).map_err(HtmlError::Link)?
+                cx.render_link(description).map_err(HtmlErrorKind::Link)?
+            },
+            Tag::FootnoteDefinition(_) => {
+                self.children_events(&tag);
+                return self.unsupported("footnotes", range)
             },
-            Tag::FootnoteDefinition(_) => 
-                return Err(HtmlError::not_implemented("footnote not implemented")),
             Tag::MetadataBlock{..} => {
-                if let Some(text) = self.children_text(tag) {
-                    cx.set_frontmatter(text)
-                }
+                cx.set_frontmatter_with_format(self.children_text(tag), FrontmatterFormat::Yaml);
                 cx.el_empty()
             }
         }
@@ -491,3 +1470,53 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_theme(){
+        assert!(matches!(find_theme(Some("nonsense")), Err(HtmlErrorKind::Theme(name)) if name == "nonsense"));
+        assert!(find_theme(None).is_ok());
+        assert!(find_theme(Some("base16-ocean.dark")).is_ok());
+    }
+
+    #[test]
+    fn available_names(){
+        let themes = available_themes();
+        assert!(themes.contains(&DEFAULT_THEME));
+        assert!(themes.windows(2).all(|w| w[0] < w[1]));
+
+        let languages = available_languages();
+        assert!(languages.contains(&"rs") && languages.contains(&"py"));
+        assert!(languages.windows(2).all(|w| w[0] < w[1]));
+        assert!(languages.iter().all(|l| SYNTAX_SET.find_syntax_by_token(l).is_some()));
+    }
+
+    #[test]
+    fn error_messages(){
+        let e = HtmlError { kind: HtmlErrorKind::component("X", "not a valid component"), range: 3..8 };
+        assert_eq!(e.to_string(), "Custom component `X` failed: `not a valid component`");
+        assert_eq!(HtmlErrorKind::Theme("x".into()).to_string(), "unknown theme: `x`");
+    }
+
+    #[test]
+    fn quoted_component_attributes(){
+        assert!(can_be_custom_component("<Chart title=\"a > b\"/>"));
+        assert!(can_be_custom_component("<Filter if=\"x<3\">"));
+        assert!(!can_be_custom_component("<Chart/> > <b>"));
+        assert!(!can_be_custom_component("<Chart title=\"a > b/>"));
+        assert!(!can_be_custom_component("<"));
+        assert!(matches!("<Chart title=\"a > b\"/>".parse::<CustomHtmlTag>(),
+                         Ok(CustomHtmlTag::Inline(c)) if c.attributes["title"] == "a > b"));
+    }
+
+    #[cfg(feature="maths")]
+    #[test]
+    fn invalid_math_source(){
+        let source = "a $\\frac{1$ b";
+        assert_eq!(math_source(source, "\\frac{1", &MathMode::Inline, 2..11), "$\\frac{1$");
+        assert_eq!(math_source("", "x", &MathMode::Display, 0..0), "$$x$$");
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use crate::utils::{decode_entities, escape_html, is_safe_url, tokenize_html, HtmlToken};
+
+/// the tags whose content is removed with them, instead of being kept as text
+const RAW_CONTENT_TAGS: [&str; 2] = ["script", "style"];
+
+/// the attributes that hold an url
+const URL_ATTRIBUTES: [&str; 6] = ["href", "src", "action", "formaction", "poster", "xlink:href"];
+
+/// The raw html that is allowed inside the markdown.
+///
+/// The elements that are not allowed are removed, but their text is kept,
+/// except for `script` and `style` that are removed with their content.
+/// The attributes that are not allowed are removed,
+/// as well as the urls that are not relative, `http`, `https`, `mailto` or `tel`,
+/// like `javascript:` urls.
+#[derive(Clone, Debug, Default)]
+pub struct SanitizeConfig {
+    /// the allowed tags, like `b` or `div`
+    pub tags: HashSet<String>,
+    /// the allowed attributes, like `class` or `href`
+    pub attributes: HashSet<String>,
+}
+
+impl SanitizeConfig {
+    /// allows the tags `tags` with the attributes `attributes`
+    pub fn new<'s>(tags: impl IntoIterator<Item=&'s str>,
+                   attributes: impl IntoIterator<Item=&'s str>) -> Self {
+        Self {
+            tags: tags.into_iter().map(|x| x.to_ascii_lowercase()).collect(),
+            attributes: attributes.into_iter().map(|x| x.to_ascii_lowercase()).collect(),
+        }
+    }
+}
+
+/// a tag of raw html, like `<a href="x">` or `</a>`
+struct ParsedTag<'h> {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    attributes: Vec<(String, Option<&'h str>)>,
+}
+
+/// parses the tag `tag`, including its `<` and `>`.
+/// Returns None for comments, doctypes and invalid tags
+fn parse_tag(tag: &str) -> Option<ParsedTag> {
+    let inner = tag.strip_prefix('<')?.strip_suffix('>')?;
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, inner),
+    };
+    let self_closing = inner.ends_with('/');
+    let inner = inner.trim_end_matches('/');
+
+    let name_end = inner.find(|c: char| c.is_whitespace()).unwrap_or(inner.len());
+    let name = inner[..name_end].to_ascii_lowercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, remaining) = match after.chars().next() {
+                    Some(q @ ('"' | '\'')) => {
+                        let end = after[1..].find(q).map(|i| i+1).unwrap_or(after.len());
+                        (&after[1..end], after.get(end+1..).unwrap_or_default())
+                    },
+                    _ => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    }
+                };
+                rest = remaining;
+                Some(value)
+            },
+            None => None,
+        };
+        attributes.push((key, value));
+        rest = rest.trim_start();
+    }
+
+    Some(ParsedTag { name, closing, self_closing, attributes })
+}
+
+/// removes the tags and attributes of `html` that are not allowed by `config`
+pub fn sanitize(html: &str, config: &SanitizeConfig) -> String {
+    let mut result = String::with_capacity(html.len());
+    // the tag whose content is being removed, like `script`
+    let mut removed: Option<String> = None;
+
+    for token in tokenize_html(html) {
+        let range = match token {
+            HtmlToken::Text(range) => {
+                if removed.is_none() {
+                    result.push_str(&escape_html(&decode_entities(&html[range])));
+                }
+                continue
+            },
+            HtmlToken::Tag(range) => range,
+        };
+        let tag = match parse_tag(&html[range]) {
+            Some(tag) => tag,
+            None => continue,
+        };
+
+        if let Some(name) = &removed {
+            if tag.closing && &tag.name == name {
+                removed = None;
+            }
+            continue
+        }
+
+        if !config.tags.contains(&tag.name) {
+            if RAW_CONTENT_TAGS.contains(&tag.name.as_str()) && !tag.closing && !tag.self_closing {
+                removed = Some(tag.name);
+            }
+            continue
+        }
+
+        if tag.closing {
+            result.push_str(&format!("</{}>", tag.name));
+            continue
+        }
+
+        result.push('<');
+        result.push_str(&tag.name);
+        for (key, value) in tag.attributes {
+            if !config.attributes.contains(&key) {
+                continue
+            }
+            // the browser decodes the entities, like in `&#106;avascript:`
+            match value.map(decode_entities) {
+                Some(v) if URL_ATTRIBUTES.contains(&key.as_str()) && !is_safe_url(&v, false) => (),
+                Some(v) => result.push_str(&format!(" {key}=\"{}\"", escape_html(&v))),
+                None => result.push_str(&format!(" {key}")),
+            }
+        }
+        if tag.self_closing {
+            result.push('/');
+        }
+        result.push('>');
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> SanitizeConfig {
+        SanitizeConfig::new(["b", "a", "div"], ["href", "class"])
+    }
+
+    #[test]
+    fn allowed_tags(){
+        assert_eq!(sanitize("<div class=\"x\">a <b>b</b></div>", &config()),
+                   "<div class=\"x\">a <b>b</b></div>");
+    }
+
+    #[test]
+    fn iframe(){
+        assert_eq!(sanitize("<iframe src=\"https://evil.com\">text</iframe>", &config()),
+                   "text");
+    }
+
+    #[test]
+    fn script(){
+        assert_eq!(sanitize("a<script>alert('<b>')</script>b", &config()), "ab");
+        assert_eq!(sanitize("<SCRIPT>alert(1)</SCRIPT>", &config()), "");
+    }
+
+    #[test]
+    fn event_attributes(){
+        assert_eq!(sanitize("<b onclick=\"alert(1)\" class='y'>x</b>", &config()),
+                   "<b class=\"y\">x</b>");
+    }
+
+    #[test]
+    fn script_url(){
+        assert_eq!(sanitize("<a href=\" JavaScript:alert(1)\">x</a>", &config()), "<a>x</a>");
+        assert_eq!(sanitize("<a href=https://a.b>x</a>", &config()), "<a href=\"https://a.b\">x</a>");
+        assert_eq!(sanitize("<a href=\"vbscript:msgbox(1)\">x</a>", &config()), "<a>x</a>");
+        assert_eq!(sanitize("<a href=\"data:text/html,x\">x</a>", &config()), "<a>x</a>");
+    }
+
+    #[test]
+    fn encoded_script_url(){
+        assert_eq!(sanitize("<a href=\"&#106;avascript:alert(1)\">x</a>", &config()), "<a>x</a>");
+        assert_eq!(sanitize("<a href=\"java&#x09;script:alert(1)\">x</a>", &config()), "<a>x</a>");
+        assert_eq!(sanitize("<a href=\"java\tscript:alert(1)\">x</a>", &config()), "<a>x</a>");
+        assert_eq!(sanitize("<a href=\"a?b=1&amp;c=2\">x</a>", &config()), "<a href=\"a?b=1&amp;c=2\">x</a>");
+    }
+
+    #[test]
+    fn escaped_text(){
+        assert_eq!(sanitize("a > b &amp; &lt;c&gt;", &config()), "a &gt; b &amp; &lt;c&gt;");
+    }
+}
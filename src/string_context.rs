@@ -0,0 +1,821 @@
+use core::ops::Range;
+
+use crate::utils::escape_html;
+use crate::{
+    render_markdown,
+    ComponentCreationError,
+    Context,
+    CowStr,
+    ElementAttributes,
+    HtmlElement,
+    HtmlError,
+    ImageAttributes,
+    LinkAttributes,
+    LinkDescription,
+    MarkdownProps,
+    MdComponentProps,
+};
+
+/// a context that renders the markdown as a html string,
+/// for example to render it on a server, or to store it.
+///
+/// The elements don't have any click handler.
+/// The custom components are functions that return html,
+/// the others are rendered like unknown components,
+/// see [`MarkdownProps::unknown_component`].
+/// The page should include the katex stylesheet to show the maths.
+#[derive(Clone, Copy)]
+pub struct StringContext<'a> {
+    props: MarkdownProps<'a>,
+    /// the custom components, with their name
+    components: &'a [(&'a str, StringComponent)],
+    /// renders the errors, see [`Context::on_error`]
+    error_view: Option<&'a dyn Fn(&HtmlError) -> Option<String>>,
+    /// is told about every click handler, see [`StringContext::with_handler_log`]
+    handler_log: Option<&'a dyn Fn(&HtmlElement, Range<usize>, bool)>,
+    /// is told about every copy button, see [`StringContext::with_copy_log`]
+    copy_log: Option<&'a dyn Fn(String)>,
+}
+
+/// a custom component of a [`StringContext`], that returns html
+pub type StringComponent = fn(MdComponentProps<String>) -> Result<String, ComponentCreationError>;
+
+impl<'a> StringContext<'a> {
+    pub fn new(props: MarkdownProps<'a>) -> Self {
+        Self { props, components: &[], error_view: None, handler_log: None, copy_log: None }
+    }
+
+    /// a context that renders the custom components `components`
+    pub fn with_components(props: MarkdownProps<'a>, components: &'a [(&'a str, StringComponent)]) -> Self {
+        Self { props, components, error_view: None, handler_log: None, copy_log: None }
+    }
+
+    /// renders the errors with `error_view`, for example to log them.
+    /// If it returns `None`, the error is shown as usual
+    pub fn with_error_view(self, error_view: &'a dyn Fn(&HtmlError) -> Option<String>) -> Self {
+        Self { error_view: Some(error_view), ..self }
+    }
+
+    /// calls `log` with the element and the position of every click handler that is created,
+    /// and wether the click stops propagating, for example to test them.
+    /// The handlers themselves do nothing
+    pub fn with_handler_log(self, log: &'a dyn Fn(&HtmlElement, Range<usize>, bool)) -> Self {
+        Self { handler_log: Some(log), ..self }
+    }
+
+    /// gives the code blocks a copy button, see [`MarkdownProps::code_copy_button`],
+    /// and calls `log` with the text that each button copies.
+    /// The buttons themselves do nothing
+    pub fn with_copy_log(self, log: &'a dyn Fn(String)) -> Self {
+        Self { copy_log: Some(log), ..self }
+    }
+}
+
+/// renders `source` as a html string, with the options given by `props`
+pub fn render_to_html_string<'a>(source: &'a str, props: MarkdownProps<'a>) -> String {
+    render_markdown(StringContext::new(props), source)
+}
+
+/// the name of the html tag of `e`, and its attributes
+/// that don't depend on the markdown, like the `start` of a list
+fn tag(e: &HtmlElement) -> (String, String) {
+    let name = match e {
+        HtmlElement::Div => "div",
+        HtmlElement::Span => "span",
+        HtmlElement::Paragraph => "p",
+        HtmlElement::BlockQuote => "blockquote",
+        HtmlElement::Ul => "ul",
+        HtmlElement::Ol(style) => {
+            let mut attributes = format!(" type=\"{}\"", style.kind.html_type());
+            if style.start != 1 {
+                attributes.push_str(&format!(" start=\"{}\"", style.start));
+            }
+            if style.reversed {
+                attributes.push_str(" reversed");
+            }
+            return ("ol".to_string(), attributes)
+        },
+        HtmlElement::Li => "li",
+        HtmlElement::Heading(level) => return (format!("h{level}"), String::new()),
+        HtmlElement::Table => "table",
+        HtmlElement::Thead => "thead",
+        HtmlElement::Tbody => "tbody",
+        HtmlElement::Trow => "tr",
+        HtmlElement::Tcell => "td",
+        HtmlElement::Italics => "i",
+        HtmlElement::Bold => "b",
+        HtmlElement::StrikeThrough => "s",
+        HtmlElement::Pre => "pre",
+        HtmlElement::Code => "code",
+        HtmlElement::Sub => "sub",
+        HtmlElement::Sup => "sup",
+        HtmlElement::Kbd => "kbd",
+        HtmlElement::Mark => "mark",
+        HtmlElement::Abbr => "abbr",
+        HtmlElement::Underline => "u",
+        HtmlElement::Dl => "dl",
+        HtmlElement::Dt => "dt",
+        HtmlElement::Dd => "dd",
+        HtmlElement::Button => "button",
+        HtmlElement::Figure => "figure",
+        HtmlElement::Figcaption => "figcaption",
+        HtmlElement::Input => "input",
+        HtmlElement::Math => "span",
+        HtmlElement::Hr => "hr",
+    };
+    (name.to_string(), String::new())
+}
+
+/// the `id`, `class` and `style` attributes of an element,
+/// each preceded by a space
+fn attributes_html<H>(attributes: &ElementAttributes<H>) -> String {
+    let mut html = String::new();
+    if let Some(id) = &attributes.id {
+        html.push_str(&format!(" id=\"{}\"", escape_html(id)));
+    }
+    if !attributes.classes.is_empty() {
+        html.push_str(&format!(" class=\"{}\"", escape_html(&attributes.classes.join(" "))));
+    }
+    if let Some(style) = &attributes.style {
+        html.push_str(&format!(" style=\"{}\"", escape_html(style)));
+    }
+    html
+}
+
+impl<'a> Context<'a, 'static> for StringContext<'a> {
+    type View = String;
+    type Handler<T: 'static> = ();
+    type MouseEvent = ();
+
+    fn props(self) -> MarkdownProps<'a> {
+        self.props
+    }
+
+    fn set_frontmatter(self, _frontmatter: String) {}
+
+    fn on_error(self, error: &HtmlError) -> Option<Self::View> {
+        self.error_view.and_then(|f| f(error))
+    }
+
+    fn render_links(self, _link: LinkDescription<Self::View>)
+        -> Result<Self::View, String> {
+        Err("a string context has no custom links".to_string())
+    }
+
+    fn call_handler<T>(_callback: &Self::Handler<T>, _input: T) {}
+
+    fn make_md_handler(self, element: &HtmlElement, position: Range<usize>, stop_propagation: bool) -> Self::Handler<Self::MouseEvent> {
+        if let Some(log) = self.handler_log {
+            log(element, position, stop_propagation)
+        }
+    }
+
+    fn make_copy_handler(self, text: String) -> Option<Self::Handler<Self::MouseEvent>> {
+        let log = self.copy_log?;
+        log(text);
+        Some(())
+    }
+
+    #[cfg(feature="debug")]
+    fn send_debug_info(self, _info: Vec<String>) {}
+
+    fn el_with_attributes(self, e: HtmlElement, inside: Self::View, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        let (name, fixed_attributes) = tag(&e);
+        format!("<{name}{fixed_attributes}{}>{inside}</{name}>", attributes_html(&attributes))
+    }
+
+    fn el_span_with_inner_html(self, inner_html: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        format!("<span{}>{inner_html}</span>", attributes_html(&attributes))
+    }
+
+    fn el_div_with_inner_html(self, inner_html: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        format!("<div{}>{inner_html}</div>", attributes_html(&attributes))
+    }
+
+    fn el_hr(self, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        format!("<hr{}/>", attributes_html(&attributes))
+    }
+
+    fn el_br(self)-> Self::View {
+        "<br/>".to_string()
+    }
+
+    fn el_fragment(self, children: Vec<Self::View>) -> Self::View {
+        children.concat()
+    }
+
+    fn el_a(self, children: Self::View, href: String, attributes: LinkAttributes) -> Self::View {
+        let mut html = format!("<a href=\"{}\"", escape_html(&href));
+        for (name, value) in [("target", attributes.target), ("rel", attributes.rel), ("title", attributes.title)] {
+            if let Some(value) = value {
+                html.push_str(&format!(" {name}=\"{}\"", escape_html(&value)));
+            }
+        }
+        if !attributes.classes.is_empty() {
+            html.push_str(&format!(" class=\"{}\"", escape_html(&attributes.classes.join(" "))));
+        }
+        format!("{html}>{children}</a>")
+    }
+
+    fn el_img(self, src: String, attributes: ImageAttributes) -> Self::View {
+        let mut html = format!("<img src=\"{}\" alt=\"{}\"", escape_html(&src), escape_html(&attributes.alt));
+        if attributes.decorative {
+            html.push_str(" role=\"presentation\"");
+        }
+        if attributes.aria_hidden {
+            html.push_str(" aria-hidden=\"true\"");
+        }
+        for (name, value) in [("width", attributes.width), ("height", attributes.height)] {
+            if let Some(value) = value {
+                html.push_str(&format!(" {name}=\"{value}\""));
+            }
+        }
+        if let Some(loading) = &attributes.loading {
+            html.push_str(&format!(" loading=\"{}\"", escape_html(loading)));
+        }
+        if !attributes.classes.is_empty() {
+            html.push_str(&format!(" class=\"{}\"", escape_html(&attributes.classes.join(" "))));
+        }
+        if let Some(style) = &attributes.style {
+            html.push_str(&format!(" style=\"{}\"", escape_html(style)));
+        }
+        html + "/>"
+    }
+
+    fn el_text(self, text: CowStr<'a>) -> Self::View {
+        escape_html(&text)
+    }
+
+    fn el_input_checkbox(self, checked: bool, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        let checked = if checked {" checked"} else {""};
+        format!("<input type=\"checkbox\"{checked}{}/>", attributes_html(&attributes))
+    }
+
+    fn mount_dynamic_link(self, _rel: &str, _href: &str, _integrity: &str, _crossorigin: &str) {}
+
+    fn has_custom_component(self, name: &str) -> bool {
+        self.components.iter().any(|(n, _)| *n == name)
+    }
+
+    fn render_custom_component(self, name: &str, input: MdComponentProps<Self::View>) -> Result<Self::View, ComponentCreationError> {
+        match self.components.iter().find(|(n, _)| *n == name) {
+            Some((_, component)) => component(input),
+            None => Err(ComponentCreationError(format!("no component named `{name}`")))
+        }
+    }
+
+    fn has_custom_links(self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::default_props;
+
+    fn render(source: &str) -> String {
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        render_to_html_string(source, props)
+    }
+
+    #[test]
+    fn paragraphs(){
+        assert_eq!(render("a *b* **c**\n\nd"), "<p>a <i>b</i> <b>c</b></p><p>d</p>");
+    }
+
+    #[test]
+    fn escaped_text(){
+        assert_eq!(render("`<script>` & \"x\""), "<p><code>&lt;script&gt;</code> &amp; &quot;x&quot;</p>");
+    }
+
+    #[test]
+    fn headings_and_links(){
+        assert_eq!(render("# A b\n[x](https://a.b \"t\")"),
+                   "<h1 id=\"a-b\">A b</h1><p><a href=\"https://a.b\" title=\"t\">x</a></p>");
+    }
+
+    #[test]
+    fn soft_breaks(){
+        let render_with = |soft_break| render_to_html_string("a\nb", MarkdownProps {
+            soft_break,
+            click_tracking: false,
+            ..default_props()
+        });
+        assert_eq!(render_with(crate::SoftBreakMode::Space), "<p>a b</p>");
+        assert_eq!(render_with(crate::SoftBreakMode::Newline), "<p>a\nb</p>");
+        assert_eq!(render_with(crate::SoftBreakMode::Ignore), "<p>ab</p>");
+        assert_eq!(render_to_html_string("a\nb", MarkdownProps {
+            hard_line_breaks: true,
+            soft_break: crate::SoftBreakMode::Ignore,
+            click_tracking: false,
+            ..default_props()
+        }), "<p>a<br/>b</p>");
+    }
+
+    #[test]
+    fn lists(){
+        assert_eq!(render("3. a\n4. b"), "<ol type=\"1\" start=\"3\"><li>a</li><li>b</li></ol>");
+        assert_eq!(render("- [x] a"), "<ul><li><input type=\"checkbox\" checked/>a</li></ul>");
+    }
+
+    #[test]
+    fn nested_lists(){
+        assert_eq!(render("- a\n\n  3. b\n\n     7. c\n"),
+                   "<ul><li><p>a</p><ol type=\"1\" start=\"3\"><li><p>b</p>\
+                   <ol type=\"1\" start=\"7\"><li>c</li></ol></li></ol></li></ul>");
+        assert_eq!(render("2. a\n   - b\n   - c\n3. d\n"),
+                   "<ol type=\"1\" start=\"2\"><li>a<ul><li>b</li><li>c</li></ul></li><li>d</li></ol>");
+        assert_eq!(render("- a\n\n  3. b\n  2. c\n"),
+                   "<ul><li><p>a</p><ol type=\"1\" start=\"3\" reversed><li>b</li><li>c</li></ol></li></ul>");
+    }
+
+    #[test]
+    fn images(){
+        let props = MarkdownProps {
+            lazy_images: true,
+            ..default_props()
+        };
+        assert_eq!(render_to_html_string("![a cat](cat.png \"=200x100\")", props),
+                   "<p><img src=\"cat.png\" alt=\"a cat\" width=\"200\" height=\"100\" loading=\"lazy\"/></p>");
+    }
+
+    #[test]
+    fn figures(){
+        let props = MarkdownProps {
+            figure_with_caption: true,
+            click_tracking: false,
+            ..default_props()
+        };
+        assert_eq!(render_to_html_string("![a cat](cat.png \"My cat\")", props),
+                   "<figure><img src=\"cat.png\" alt=\"a cat\"/><figcaption>My cat</figcaption></figure>");
+        assert_eq!(render_to_html_string("![a cat](cat.png)", props),
+                   "<p><img src=\"cat.png\" alt=\"a cat\"/></p>");
+        assert_eq!(render_to_html_string("a ![a cat](cat.png \"My cat\")", props),
+                   "<p>a <figure><img src=\"cat.png\" alt=\"a cat\"/><figcaption>My cat</figcaption></figure></p>");
+    }
+
+    #[test]
+    fn toml_frontmatter(){
+        assert_eq!(render("+++\ntitle = \"a\"\n+++\nb"), "<p>b</p>");
+    }
+
+    #[test]
+    fn client_side_highlighting(){
+        let props = MarkdownProps {
+            highlight_mode: crate::HighlightMode::ClientSide,
+            ..default_props()
+        };
+        let html = render_to_html_string("```rust\nlet a = b < c && d;\n```", props);
+        assert_eq!(html, "<pre><code class=\"language-rust\">let a = b &lt; c &amp;&amp; d;\n</code></pre>");
+    }
+
+    #[test]
+    fn code_titles(){
+        let props = MarkdownProps {
+            highlight_mode: crate::HighlightMode::ClientSide,
+            ..default_props()
+        };
+        let html = render_to_html_string("```rust title=\"main.rs\"\nfn main(){}\n```", props);
+        assert_eq!(html, "<div class=\"code-block\"><div class=\"code-title\">main.rs</div>\
+                          <pre><code class=\"language-rust\">fn main(){}\n</code></pre></div>");
+        let html = render_to_html_string("```title=\"a.txt\"\nb\n```", props);
+        assert_eq!(html, "<div class=\"code-block\"><div class=\"code-title\">a.txt</div>\
+                          <pre><code>b\n</code></pre></div>");
+    }
+
+    #[test]
+    fn inline_code_highlighting(){
+        let props = MarkdownProps {
+            inline_code_highlight: true,
+            click_tracking: false,
+            ..default_props()
+        };
+        let html = render_to_html_string("a `rust:let x = 1` b", props);
+        assert!(html.starts_with("<p>a <span><code style=\"background-color:"));
+        assert!(html.contains("<span style=") && !html.contains("rust:"));
+        assert_eq!(render_to_html_string("`nolang:x` `zz:b c`", props),
+                   "<p><code>nolang:x</code> <code>zz:b c</code></p>");
+
+        let props = MarkdownProps {
+            highlight_mode: crate::HighlightMode::ClientSide,
+            ..props
+        };
+        assert_eq!(render_to_html_string("`rust:let x = 1`", props),
+                   "<p><code class=\"language-rust\">let x = 1</code></p>");
+    }
+
+    #[test]
+    fn dark_theme(){
+        let props = MarkdownProps {
+            dark_theme: Some("base16-ocean.dark"),
+            ..default_props()
+        };
+        let html = render_to_html_string("```rust\nfn a(){}\n```", props);
+        let (light, dark) = html.split_once("</div><div class=\"dark-theme\">").unwrap();
+        assert!(light.starts_with("<div class=\"light-theme\"><span><pre style="));
+        assert!(dark.starts_with("<span><pre style="));
+        assert_ne!(light.replace("<div class=\"light-theme\">", ""), dark.trim_end_matches("</div>"));
+
+        let props = MarkdownProps {
+            highlight_mode: crate::HighlightMode::ClientSide,
+            ..props
+        };
+        let html = render_to_html_string("```rust\nfn a(){}\n```", props);
+        assert!(!html.contains("dark-theme"));
+    }
+
+    #[test]
+    fn theme_value(){
+        use syntect::highlighting::{Color, Theme, ThemeSettings};
+        let theme = Theme {
+            settings: ThemeSettings {
+                background: Some(Color { r: 0x12, g: 0x34, b: 0x56, a: 0xff }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let props = MarkdownProps {
+            theme: Some("nonsense"),
+            theme_value: Some(&theme),
+            ..default_props()
+        };
+        let html = render_to_html_string("```rust\nfn a(){}\n```", props);
+        assert!(html.contains("background-color:#123456;"));
+        assert!(!html.contains("markdown-error"));
+    }
+
+    #[test]
+    fn custom_syntax_set(){
+        let syntaxes = syntect::parsing::SyntaxSet::default();
+        let props = MarkdownProps {
+            syntax_set: Some(&syntaxes),
+            ..default_props()
+        };
+        // the set has no grammar for rust
+        let html = render_to_html_string("```rust\nfn a(){}\n```", props);
+        assert_eq!(html, "<code><code>fn a(){}\n</code></code>");
+    }
+
+    #[cfg(feature="maths")]
+    #[test]
+    fn math_output(){
+        let render_with = |math_output| render_to_html_string("$x^2$", MarkdownProps {
+            math_output,
+            ..default_props()
+        });
+        let html = render_with(crate::MathOutput::HtmlAndMathMl);
+        assert!(html.contains("<math") && html.contains("katex-html"));
+        let html = render_with(crate::MathOutput::MathMlOnly);
+        assert!(html.contains("<math") && !html.contains("katex-html"));
+        let html = render_with(crate::MathOutput::HtmlOnly);
+        assert!(!html.contains("<math") && html.contains("katex-html"));
+    }
+
+    #[cfg(feature="maths")]
+    #[test]
+    fn invalid_math(){
+        let source = "a $\\frac{1$ b";
+        let html = render(source);
+        assert!(html.contains("<span class=\"error-math\">$\\frac{1$</span>"));
+
+        let errors = std::cell::RefCell::new(Vec::new());
+        let log = |e: &HtmlError| {
+            errors.borrow_mut().push((matches!(e.kind, crate::HtmlErrorKind::Math(_)), e.range.clone()));
+            Some(String::new())
+        };
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        let html = render_markdown(StringContext::new(props).with_error_view(&log), source);
+        assert_eq!(*errors.borrow(), vec![(true, 2..11)]);
+        assert_eq!(html, "<p>a  b</p>");
+    }
+
+    #[cfg(all(feature="client-maths", not(feature="maths")))]
+    #[test]
+    fn client_maths(){
+        assert_eq!(render("$x < 2$"), "<p><span class=\"math math-inline\">\\(x &lt; 2\\)</span></p>");
+        assert_eq!(render("$$\\frac{1}{2}$$"), "<p><span class=\"math math-flow\">\\[\\frac{1}{2}\\]</span></p>");
+    }
+
+    #[cfg(not(any(feature="maths", feature="client-maths")))]
+    #[test]
+    fn maths_without_feature(){
+        let error = "<span class=\"markdown-error\">`maths, without the `maths` feature`: not implemented</span>";
+        assert_eq!(render("a $x$ b"), format!("<p>a {error} b</p>"));
+        assert_eq!(render("$$\\frac{1}{2}$$"), format!("<p>{error}</p>"));
+    }
+
+    #[test]
+    fn mermaid(){
+        assert_eq!(render("```mermaid\ngraph TD\n  a --> b\n```"),
+                   "<pre class=\"mermaid\">graph TD\n  a --&gt; b\n</pre>");
+    }
+
+    #[test]
+    fn links_in_text(){
+        let props = MarkdownProps {
+            linkify: true,
+            click_tracking: false,
+            ..default_props()
+        };
+        assert_eq!(render_to_html_string("see www.a.b or a@b.c", props),
+                   "<p>see <a href=\"http://www.a.b\">www.a.b</a> or <a href=\"mailto:a@b.c\">a@b.c</a></p>");
+        assert_eq!(render("<a@b.c>"), "<p><a href=\"mailto:a@b.c\">a@b.c</a></p>");
+    }
+
+    #[test]
+    fn errors(){
+        assert_eq!(render("<Widget/>\n"),
+                   "<div class=\"markdown-error\">Custom component `Widget` failed: `not a valid component`</div>");
+    }
+
+    /// a component that shows its position in the source
+    fn position(props: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+        let range = props.range();
+        Ok(format!("<i>{}..{}</i>{}", range.start, range.end, props.children))
+    }
+
+    fn render_with_position(source: &str) -> String {
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        let components: &[(&str, StringComponent)] = &[("Pos", position)];
+        render_markdown(StringContext::with_components(props, components), source)
+    }
+
+    #[test]
+    fn component_ids(){
+        fn echo(props: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+            Ok(format!("<b>{}</b>", props.attributes.get("id").map(String::as_str).unwrap_or("none")))
+        }
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        let components: &[(&str, StringComponent)] = &[("Echo", echo)];
+        let render = |source| render_markdown(StringContext::with_components(props, components), source);
+        assert_eq!(render("<Echo id=\"sales\"/>\n"), "<div id=\"sales\"><b>sales</b></div>");
+        assert_eq!(render("a <Echo id=\"x\"/>"), "<p>a <span id=\"x\"><b>x</b></span></p>");
+        assert_eq!(render("<Echo/>\n"), "<b>none</b>");
+    }
+
+    #[test]
+    fn component_ranges(){
+        assert_eq!(render_with_position("a\n\n<Pos k=\"v\"/>\n"), "<p>a</p><i>3..15</i>");
+        assert_eq!(render_with_position("a <Pos/> b"), "<p>a <i>2..8</i> b</p>");
+        assert_eq!(render_with_position("<Pos/>\n<Pos/>\n"), "<i>0..6</i><i>7..13</i>");
+        assert_eq!(render_with_position("<Pos>\n\nb\n\n</Pos>\n"), "<i>0..5</i><p>b</p>");
+    }
+
+    #[test]
+    fn component_paragraphs(){
+        let props = MarkdownProps {
+            click_tracking: false,
+            unwrap_component_paragraphs: true,
+            ..default_props()
+        };
+        let components: &[(&str, StringComponent)] = &[("Pos", position)];
+        let unwrapped = |source| render_markdown(StringContext::with_components(props, components), source);
+
+        assert_eq!(render_with_position("<Pos/>"), "<i>0..6</i>");
+        assert_eq!(unwrapped("<Pos/>"), "<i>0..6</i>");
+        assert_eq!(render_with_position("z<Pos/>"), "<p>z<i>1..7</i></p>");
+        assert_eq!(unwrapped("z<Pos/>"), "<p>z<i>1..7</i></p>");
+        assert_eq!(render_with_position("<Pos/><Pos/>"), "<p><i>0..6</i><i>6..12</i></p>");
+        assert_eq!(unwrapped("<Pos/><Pos/>"), "<i>0..6</i><i>6..12</i>");
+    }
+
+    #[test]
+    fn clicked_elements(){
+        let elements = std::cell::RefCell::new(Vec::new());
+        let log = |e: &HtmlElement, _: Range<usize>, _: bool| elements.borrow_mut().push(e.clone());
+        let clicked = |source| {
+            elements.borrow_mut().clear();
+            render_markdown(StringContext::new(default_props()).with_handler_log(&log), source);
+            elements.borrow().clone()
+        };
+        assert_eq!(clicked("## a\n\nb `c`\n\n```\nd\n```"),
+                   vec![HtmlElement::Heading(2), HtmlElement::Paragraph, HtmlElement::Code, HtmlElement::Pre]);
+        assert_eq!(clicked("> *a*\n\n- b\n\n---"),
+                   vec![HtmlElement::Paragraph, HtmlElement::Li, HtmlElement::Hr]);
+        #[cfg(any(feature="maths", feature="client-maths"))]
+        assert_eq!(clicked("a $b$"), vec![HtmlElement::Paragraph, HtmlElement::Math]);
+    }
+
+    #[test]
+    fn code_block_propagation(){
+        let stops = std::cell::RefCell::new(Vec::new());
+        let log = |_: &HtmlElement, _: Range<usize>, stop: bool| stops.borrow_mut().push(stop);
+        let render_with = |stop_propagation| {
+            let props = MarkdownProps {
+                stop_propagation,
+                ..default_props()
+            };
+            stops.borrow_mut().clear();
+            render_markdown(StringContext::new(props).with_handler_log(&log), "a\n\n```\nc\n```\n\n---");
+            stops.borrow().clone()
+        };
+        // the text, the code block and the rule
+        assert_eq!(render_with(None), vec![false, true, false]);
+        let never = |_: &HtmlElement| false;
+        assert_eq!(render_with(Some(&never)), vec![false, false, false]);
+        let code_blocks_and_rules = |e: &HtmlElement| matches!(e, HtmlElement::Pre | HtmlElement::Hr);
+        assert_eq!(render_with(Some(&code_blocks_and_rules)), vec![false, true, true]);
+    }
+
+    #[test]
+    fn copy_button(){
+        let copied = std::cell::RefCell::new(Vec::new());
+        let log = |text: String| copied.borrow_mut().push(text);
+        let render_with = |code_copy_button| {
+            let props = MarkdownProps {
+                code_copy_button,
+                click_tracking: false,
+                ..default_props()
+            };
+            render_markdown(StringContext::new(props).with_copy_log(&log), "```\nlet x = 1;\n```")
+        };
+        assert!(!render_with(false).contains("copy-button"));
+        assert!(copied.borrow().is_empty());
+        assert!(render_with(true).contains("<button class=\"copy-button\""));
+        assert_eq!(*copied.borrow(), vec!["let x = 1;\n".to_string()]);
+    }
+
+    #[test]
+    fn class_map(){
+        let class_map = crate::ClassMap::new()
+            .add(HtmlElement::Table, "table")
+            .add(HtmlElement::Table, "table-striped")
+            .add(HtmlElement::Heading(2), "title");
+        let props = MarkdownProps {
+            class_map: Some(&class_map),
+            click_tracking: false,
+            ..default_props()
+        };
+        let html = render_to_html_string("# a\n\n## b\n\n| c |\n|---|\n| d |", props);
+        assert!(html.starts_with("<h1 id=\"a\">a</h1><h2 id=\"b\" class=\"title\">b</h2><table class=\"table table-striped\">"));
+    }
+
+    #[test]
+    fn external_links(){
+        let props = MarkdownProps {
+            external_links_new_tab: true,
+            site_url: Some("https://example.com"),
+            click_tracking: false,
+            ..default_props()
+        };
+        assert_eq!(render_to_html_string("[a](https://rust-lang.org)", props),
+                   "<p><a href=\"https://rust-lang.org\" target=\"_blank\" rel=\"noopener noreferrer\">a</a></p>");
+        assert_eq!(render_to_html_string("[a](/docs)", props), "<p><a href=\"/docs\">a</a></p>");
+        assert_eq!(render_to_html_string("[a](https://example.com/docs)", props),
+                   "<p><a href=\"https://example.com/docs\">a</a></p>");
+        assert_eq!(render("[a](https://rust-lang.org)"), "<p><a href=\"https://rust-lang.org\">a</a></p>");
+    }
+
+    #[test]
+    fn ragged_table(){
+        let html = render("| a | b |\n|:-|-:|\n| 1 | 2 | 3 |\n| 4 |");
+        assert!(html.contains("<td style=\"text-align: left\">1</td><td style=\"text-align: right\">2</td>"));
+        assert!(html.contains("<td style=\"text-align: left\">4</td>"));
+    }
+
+    #[test]
+    fn nesting_depth(){
+        let source = ">".repeat(10_000) + " a";
+        let html = render(&source);
+        assert!(html.contains("the elements are nested too deeply"));
+        assert!(html.starts_with("<blockquote><blockquote>"));
+
+        let props = MarkdownProps {
+            max_depth: 2,
+            click_tracking: false,
+            ..default_props()
+        };
+        assert_eq!(render_to_html_string("> > a\n\nb", props),
+                   "<blockquote><blockquote><div class=\"markdown-error\">the elements are nested too deeply</div></blockquote></blockquote><p>b</p>");
+    }
+
+    #[test]
+    fn component_error_ranges(){
+        fn failing(_: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+            Err(ComponentCreationError("failed".to_string()))
+        }
+        let ranges = std::cell::RefCell::new(Vec::new());
+        let log = |e: &HtmlError| {
+            ranges.borrow_mut().push(e.range.clone());
+            None
+        };
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        let components: &[(&str, StringComponent)] = &[("Fail", failing)];
+        let cx = StringContext::with_components(props, components).with_error_view(&log);
+        let html = render_markdown(cx, "a\n\n<Fail k=\"v\"/>\n\nb <Fail/>");
+        assert!(html.contains("Custom component `Fail` failed: `failed`"));
+        assert_eq!(*ranges.borrow(), vec![3..16, 20..27]);
+    }
+
+    #[test]
+    fn nested_start_tags(){
+        fn card(props: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+            Ok(format!("<div>{}</div>", props.children))
+        }
+        let props = MarkdownProps {
+            click_tracking: false,
+            ..default_props()
+        };
+        let components: &[(&str, StringComponent)] = &[("Card", card)];
+        let render = |source| render_markdown(StringContext::with_components(props, components), source);
+        assert_eq!(render("<Card>\n\n<Card>\n\nx\n\n</Card>\n\n</Card>\n"), "<div><div><p>x</p></div></div>");
+        assert_eq!(render("<Card>\n<Card>\n\nx\n"), "<div></div><div></div><p>x</p>");
+    }
+
+    #[test]
+    fn wrapped_block_components(){
+        assert_eq!(render_with_position("<Pos/>\n"), "<i>0..6</i>");
+        assert_eq!(render_with_position("a<Pos/>\n"), "<p>a<i>1..7</i></p>");
+        assert_eq!(render_with_position("<Pos/>\n<Pos/>\n"), "<i>0..6</i><i>7..13</i>");
+
+        let props = MarkdownProps {
+            click_tracking: false,
+            wrap_block_components: true,
+            ..default_props()
+        };
+        let components: &[(&str, StringComponent)] = &[("Pos", position)];
+        let render = |source| render_markdown(StringContext::with_components(props, components), source);
+        assert_eq!(render("<Pos/>\n"), "<p><i>0..6</i></p>");
+        assert_eq!(render("<Pos/>\n<Pos/>\n"), "<p><i>0..6</i><i>7..13</i></p>");
+        // a paragraph with inline html, not an html block
+        assert_eq!(render("<Pos/><Pos/>\n"), "<p><i>0..6</i><i>6..12</i></p>");
+        assert_eq!(render("a<Pos/>\n"), "<p>a<i>1..7</i></p>");
+        assert_eq!(render("<Pos>\n\nb\n\n</Pos>\n"), "<i>0..5</i><p>b</p>");
+    }
+
+    #[test]
+    fn unclosed_component(){
+        let html = render_with_position("<Pos>\n\nb\n");
+        assert!(html.contains("Custom component `Pos` failed: `not closed`"));
+        assert!(html.ends_with("<p>b</p>"));
+        assert!(!html.contains("<i>"));
+
+        let html = render_with_position("> <Pos>\n>\n> b\n\nc");
+        assert!(html.contains("`not closed`"));
+        assert!(html.ends_with("<p>c</p>"));
+    }
+
+    #[test]
+    fn component_inner_source(){
+        const SOURCE: &str = "<Raw>\n\n- a\n- b\n\n</Raw>\n\n<Raw/>";
+        fn raw(props: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+            Ok(format!("<pre>{}</pre>", escape_html(SOURCE[props.inner_range()].trim_end())))
+        }
+        let components: &[(&str, StringComponent)] = &[("Raw", raw)];
+        let html = render_markdown(StringContext::with_components(default_props(), components), SOURCE);
+        assert_eq!(html, "<pre>- a\n- b</pre><pre></pre>");
+    }
+
+    #[test]
+    fn adjacent_components(){
+        assert_eq!(render_with_position("<Pos><Pos>\n"), "<p><i>0..5</i><i>5..10</i></p>");
+        assert_eq!(render_with_position("<Pos>\n<Pos>\n"), "<i>0..5</i><i>6..11</i>");
+    }
+
+    #[test]
+    fn html_block(){
+        let config = crate::SanitizeConfig::new(["div"], ["class"]);
+        let props = MarkdownProps {
+            sanitize: Some(&config),
+            ..default_props()
+        };
+        let html = render_to_html_string("<div class=\"note\" onclick=\"x()\"><iframe>a</iframe></div>\n", props);
+        assert_eq!(html, "<div><div class=\"note\">a</div>\n</div>");
+    }
+
+    #[test]
+    fn multiline_html_block(){
+        let config = crate::SanitizeConfig::new(["div"], ["class"]);
+        let props = MarkdownProps {
+            sanitize: Some(&config),
+            click_tracking: false,
+            ..default_props()
+        };
+        let html = render_to_html_string("<div class=\"note\">\n\n*a*\n\n</div>\n", props);
+        assert!(!html.contains("markdown-error"));
+        assert!(html.starts_with("<div><div class=\"note\">"));
+        assert!(html.contains("<p><i>a</i></p>"));
+    }
+
+    #[test]
+    fn tracked_text(){
+        let html = render_to_html_string("a", default_props());
+        assert_eq!(html, "<p><span>a</span></p>");
+    }
+}
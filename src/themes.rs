@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::LoadingError;
+
+/// Syntax highlighting themes added to the default themes of syntect,
+/// like the color scheme of an editor.
+///
+/// Give it to the renders with [`crate::MarkdownProps::themes`]:
+/// the theme named by [`crate::MarkdownProps::theme`] is looked up
+/// in the registry before the default themes.
+#[derive(Default)]
+pub struct ThemeRegistry {
+    themes: BTreeMap<String, Theme>,
+}
+
+impl ThemeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds the theme `theme` under the name `name`
+    pub fn register(&mut self, name: impl ToString, theme: Theme) {
+        self.themes.insert(name.to_string(), theme);
+    }
+
+    /// parses the content of a `.tmTheme` file
+    /// and adds it under the name `name`
+    pub fn register_tm_theme(&mut self, name: impl ToString, tm_theme: &[u8])
+        -> Result<(), LoadingError> {
+        let theme = ThemeSet::load_from_reader(&mut Cursor::new(tm_theme))?;
+        self.register(name, theme);
+        Ok(())
+    }
+
+    /// returns the theme registered as `name`
+    pub fn get(&self, name: &str) -> Option<&Theme> {
+        self.themes.get(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registered_theme(){
+        let mut registry = ThemeRegistry::new();
+        let theme = Theme {
+            name: Some("mine".to_string()),
+            ..Default::default()
+        };
+        registry.register("mine", theme);
+        assert_eq!(registry.get("mine").and_then(|t| t.name.as_deref()), Some("mine"));
+        assert!(registry.get("other").is_none());
+    }
+
+    #[test]
+    fn invalid_tm_theme(){
+        let mut registry = ThemeRegistry::new();
+        assert!(registry.register_tm_theme("bad", b"not a plist").is_err());
+        assert!(registry.get("bad").is_none());
+    }
+}
@@ -1,4 +1,10 @@
-use pulldown_cmark_wikilink::{Tag, TagEnd};
+use core::ops::{Range, RangeInclusive};
+use core::iter::Peekable;
+use std::collections::{HashMap, HashSet, VecDeque};
+use pulldown_cmark_wikilink::{CowStr, Event, HeadingLevel, LinkType, Options, ParserOffsetIter, Tag, TagEnd};
+
+use crate::component::CustomHtmlTag;
+use crate::{FrontmatterFormat, SoftBreakMode};
 
 pub fn as_closing_tag(t: &Tag) -> TagEnd {
     match t {
@@ -23,3 +29,1379 @@ pub fn as_closing_tag(t: &Tag) -> TagEnd {
     }
 }
 
+/// replaces every soft break by a hard break,
+/// except inside headings and table cells where a `<br>` is not wanted.
+pub fn soft_to_hard_breaks<'a>(stream: impl Iterator<Item=(Event<'a>, Range<usize>)>)
+    -> impl Iterator<Item=(Event<'a>, Range<usize>)> {
+    // number of currently open tags where hard breaks are not allowed
+    let mut forbidden_depth = 0;
+    stream.map(move |(e, range)| {
+        match e {
+            Event::Start(Tag::Heading{..}) | Event::Start(Tag::TableCell)
+                => forbidden_depth += 1,
+            Event::End(TagEnd::Heading(_)) | Event::End(TagEnd::TableCell)
+                => forbidden_depth -= 1,
+            Event::SoftBreak if forbidden_depth == 0
+                => return (Event::HardBreak, range),
+            _ => ()
+        }
+        (e, range)
+    })
+}
+
+/// replaces the soft breaks of `stream` by a text, a space or a newline,
+/// or removes them, depending on `mode`
+pub fn replace_soft_breaks<'a>(stream: impl Iterator<Item=(Event<'a>, Range<usize>)>, mode: SoftBreakMode)
+    -> impl Iterator<Item=(Event<'a>, Range<usize>)> {
+    let text = match mode {
+        SoftBreakMode::Space => Some(" "),
+        SoftBreakMode::Newline => Some("\n"),
+        SoftBreakMode::Ignore => None,
+    };
+    stream.filter_map(move |(e, range)| match e {
+        Event::SoftBreak => text.map(|t| (Event::Text(t.into()), range)),
+        e => Some((e, range))
+    })
+}
+
+/// concatenates the text events of `stream` until the closing tag `end`.
+/// Breaks are converted to newlines.
+/// The text stops at the first unexpected event, like a formula inside a heading
+/// or the end of a document being typed, but the events are consumed
+/// until the closing tag, so that the stream stays consistent.
+pub fn collect_text<'a>(stream: &mut impl Iterator<Item=(Event<'a>, Range<usize>)>, end: TagEnd)
+    -> String {
+    let mut text = String::new();
+    let mut complete = true;
+    // number of tags opened since the start
+    let mut depth = 0;
+    for (e, _) in stream.by_ref() {
+        match e {
+            Event::Text(s) if complete => text.push_str(&s),
+            Event::SoftBreak | Event::HardBreak if complete => text.push('\n'),
+            Event::End(e) if depth == 0 && e == end => break,
+            Event::Start(_) => {
+                complete = false;
+                depth += 1
+            },
+            Event::End(_) => {
+                complete = false;
+                depth = (depth - 1).max(0)
+            },
+            _ => complete = false,
+        }
+    }
+    text
+}
+
+/// extracts the events of `stream` until the closing tag `end`.
+/// The closing tag is consumed but not returned.
+pub fn collect_events<'a>(stream: &mut impl Iterator<Item=(Event<'a>, Range<usize>)>, end: TagEnd)
+    -> Vec<(Event<'a>, Range<usize>)> {
+    let mut events = Vec::new();
+    // number of tags opened since the start
+    let mut depth = 0;
+    while let Some((e, range)) = stream.next() {
+        match &e {
+            Event::Start(_) => depth += 1,
+            Event::End(t) if depth == 0 => {
+                debug_assert!(*t == end, "{t:?} is a wrong closing tag");
+                break
+            },
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+        events.push((e, range));
+    }
+    events
+}
+
+/// the text content of `events`, without any formatting
+pub fn plain_text(events: &[(Event, Range<usize>)]) -> String {
+    events.iter()
+        .filter_map(|(e, _)| match e {
+            Event::Text(s) | Event::Code(s) => Some(&**s),
+            Event::SoftBreak | Event::HardBreak => Some(" "),
+            _ => None
+        })
+        .collect()
+}
+
+/// if `events` only contain inline html and whitespace,
+/// returns the concatenated html
+pub fn only_inline_html(events: &[(Event, Range<usize>)]) -> Option<String> {
+    let mut html = String::new();
+    for (e, _) in events {
+        match e {
+            Event::InlineHtml(s) => html.push_str(s),
+            Event::Text(s) if s.trim().is_empty() => (),
+            Event::SoftBreak | Event::HardBreak => html.push(' '),
+            _ => return None
+        }
+    }
+    Some(html)
+}
+
+/// the frontmatter at the top of `source` that the parser doesn't recognize:
+/// toml between `+++` lines, or json between `--- json` and `---` lines.
+/// A document starting with `{` is markdown, not json.
+/// Returns its format, its content and the position where the markdown starts
+pub fn extra_frontmatter(source: &str) -> Option<(FrontmatterFormat, &str, usize)> {
+    let mut lines = source.split_inclusive('\n');
+    let first = lines.next()?;
+    let (format, closing) = match first.trim_end() {
+        "+++" => (FrontmatterFormat::Toml, "+++"),
+        "---json" | "--- json" => (FrontmatterFormat::Json, "---"),
+        _ => return None
+    };
+    let mut offset = first.len();
+    for line in lines {
+        let end = offset + line.len();
+        if line.trim_end() == closing {
+            return Some((format, &source[first.len()..offset], end))
+        }
+        offset = end;
+    }
+    None
+}
+
+/// the page of the wikilink with the markdown source `link`,
+/// like `Page` for `[[Page|text]]`.
+/// Returns None if `link` is not a wikilink
+pub fn wikilink_page(link: &str) -> Option<String> {
+    let inner = link.strip_prefix("[[")?.strip_suffix("]]")?;
+    let page = match inner.split_once('|') {
+        Some((page, _)) => page,
+        None => inner,
+    };
+    Some(page.trim().to_string())
+}
+
+/// the level of a heading of level `level` once shifted by `offset`,
+/// at most 6
+pub fn offset_heading_level(level: HeadingLevel, offset: u8) -> u8 {
+    (level as u8).saturating_add(offset).min(6)
+}
+
+/// the size of an image, given by the end of its title, like `=200x100`
+/// (or `=200x` and `=x100` for a single dimension),
+/// or by the `width` and `height` parameters of its url, like `cat.png?width=200`.
+/// The title has priority over the url.
+pub fn image_size(title: &str, url: &str) -> (Option<u32>, Option<u32>) {
+    let from_title = title_size(title)
+        .and_then(|x| x.strip_prefix('='))
+        .and_then(|x| x.split_once('x'))
+        .map(|(w, h)| (w.parse().ok(), h.parse().ok()));
+    if let Some(size) = from_title {
+        return size
+    }
+
+    let query = match url.split_once('?') {
+        Some((_, query)) => query.split('#').next().unwrap_or_default(),
+        None => return (None, None),
+    };
+    let mut size = (None, None);
+    for (key, value) in query.split('&').filter_map(|x| x.split_once('=')) {
+        match key {
+            "width" => size.0 = value.parse().ok(),
+            "height" => size.1 = value.parse().ok(),
+            _ => ()
+        }
+    }
+    size
+}
+
+/// the size at the end of the title of an image, like `=200x100`
+fn title_size(title: &str) -> Option<&str> {
+    title.split_whitespace()
+        .last()
+        .filter(|x| x.starts_with('=') && x.contains('x'))
+}
+
+/// the caption of an image with the title `title`,
+/// without the size given by [`image_size`]
+pub fn image_caption(title: &str) -> &str {
+    match title_size(title) {
+        Some(size) => title.trim_end().strip_suffix(size).unwrap_or(title).trim(),
+        None => title.trim(),
+    }
+}
+
+/// returns true if `events`, the content of a paragraph,
+/// are a single image with a caption, see [`image_caption`]
+pub fn is_captioned_image(events: &[(Event, Range<usize>)]) -> bool {
+    match events.first() {
+        Some((Event::Start(Tag::Image{title, ..}), _)) if !image_caption(title).is_empty() => (),
+        _ => return false
+    }
+    let mut depth = 0;
+    for (i, (e, _)) in events.iter().enumerate() {
+        match e {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+        if depth == 0 {
+            return i == events.len() - 1
+        }
+    }
+    false
+}
+
+/// the kinds of github alerts, like `> [!NOTE]`
+const ADMONITION_KINDS: [&str; 5] = ["note", "tip", "important", "warning", "caution"];
+
+/// if the block quote with the events `events` is a github alert,
+/// like `> [!NOTE]`, removes the `[!NOTE]` marker from the events
+/// and returns the kind of the alert, like `note`
+pub fn strip_admonition<'a>(events: &mut Vec<(Event<'a>, Range<usize>)>) -> Option<&'static str> {
+    if !matches!(events.first(), Some((Event::Start(Tag::Paragraph), _))) {
+        return None
+    }
+    // the marker can be split into several texts, like `[`, `!NOTE` and `]`
+    let mut marker = String::new();
+    let mut n_texts = 0;
+    for (e, _) in &events[1..] {
+        match e {
+            Event::Text(s) => marker.push_str(s),
+            _ => break
+        }
+        n_texts += 1;
+        if marker.contains(']') {
+            break
+        }
+    }
+    let (kind, rest) = marker.trim_start()
+        .strip_prefix("[!")?
+        .split_once(']')?;
+    let kind = ADMONITION_KINDS.into_iter()
+        .find(|k| k.eq_ignore_ascii_case(kind))?;
+
+    let marker_range = events[n_texts].1.clone();
+    events.drain(1..1+n_texts);
+    let rest = rest.trim_start();
+    if !rest.is_empty() {
+        events.insert(1, (Event::Text(rest.to_string().into()), marker_range));
+    }
+    else if matches!(events.get(1), Some((Event::SoftBreak | Event::HardBreak, _))) {
+        events.remove(1);
+    }
+    if matches!(events.get(1), Some((Event::End(TagEnd::Paragraph), _))) {
+        events.drain(0..2);
+    }
+    Some(kind)
+}
+
+/// returns true if `raw_html` is the closing tag `</name>`
+fn is_closing_tag_of(raw_html: &str, name: &str) -> bool {
+    matches!(raw_html.parse::<CustomHtmlTag>(), Ok(CustomHtmlTag::End(n)) if n == name)
+}
+
+/// returns true if `raw_html` is the opening tag `<name>`
+fn is_opening_tag_of(raw_html: &str, name: &str) -> bool {
+    matches!(raw_html.parse::<CustomHtmlTag>(), Ok(CustomHtmlTag::Start(c)) if c.name == name)
+}
+
+/// how the events of a custom component ended, see [`component_events`]
+#[derive(Debug, PartialEq)]
+pub enum ComponentEnd {
+    /// with the html block `</name>`
+    Closed,
+    /// with the end of the parent element, like a block quote, without closing tag
+    ParentEnded,
+    /// with the end of the document, without closing tag
+    Unclosed,
+}
+
+/// extracts the events of `stream` until the html block `</name>`
+/// that closes the custom component `name`, whose start was already consumed.
+/// Components with the same name can be nested inside it,
+/// like `<Card>` inside `<Card>`.
+/// Returns the events and how they ended
+pub fn component_events<'a>(stream: &mut impl Iterator<Item=(Event<'a>, Range<usize>)>, name: &str)
+    -> (Vec<(Event<'a>, Range<usize>)>, ComponentEnd) {
+    let mut events = Vec::new();
+    // number of tags opened since the start of the component
+    let mut depth = 0;
+    // number of components `name` opened inside the component
+    let mut nesting = 0;
+    while let Some((e, range)) = stream.next() {
+        let in_html_block = depth == 1
+            && matches!(events.last(), Some((Event::Start(Tag::HtmlBlock), _)));
+        match &e {
+            Event::Html(s) if in_html_block && is_opening_tag_of(s, name) => nesting += 1,
+            Event::Html(s) if in_html_block && is_closing_tag_of(s, name) => {
+                if nesting == 0 {
+                    events.pop();
+                    // the end of the html block
+                    stream.next();
+                    return (events, ComponentEnd::Closed)
+                }
+                nesting -= 1;
+            },
+            Event::Start(_) => depth += 1,
+            Event::End(_) if depth == 0 => return (events, ComponentEnd::ParentEnded),
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+        events.push((e, range));
+    }
+    (events, ComponentEnd::Unclosed)
+}
+
+/// returns true if the numbers of the items of an ordered list decrease,
+/// like `3. 2. 1.`. `events` are the events inside the list
+pub fn is_reversed_list(source: &str, events: &[(Event, Range<usize>)]) -> bool {
+    let mut numbers = Vec::new();
+    let mut depth = 0;
+    for (e, range) in events {
+        match e {
+            Event::Start(t) => {
+                if depth == 0 && *t == Tag::Item {
+                    numbers.push(source.get(range.start..).and_then(item_number));
+                }
+                depth += 1
+            },
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+        if numbers.len() == 2 {
+            break
+        }
+    }
+    matches!(numbers[..], [Some(a), Some(b)] if b < a)
+}
+
+/// the number of the list item starting `item`, like 3 in `3. hello`
+fn item_number(item: &str) -> Option<u64> {
+    item.trim_start()
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// splits the events inside a table: `events` keeps the head of the table,
+/// and the rows of the body are returned
+pub fn split_table_head<'a>(events: &mut Vec<(Event<'a>, Range<usize>)>)
+    -> Vec<(Event<'a>, Range<usize>)> {
+    let head_end = events.iter()
+        .position(|(e, _)| *e == Event::End(TagEnd::TableHead))
+        .map(|i| i+1)
+        .unwrap_or(0);
+    events.split_off(head_end)
+}
+
+/// a piece of raw html: either a tag or the text between tags.
+/// It holds the position of the piece inside the html.
+#[derive(Debug, PartialEq)]
+pub enum HtmlToken {
+    Tag(Range<usize>),
+    Text(Range<usize>),
+}
+
+/// splits `html` into tags and text.
+/// A `>` inside a quoted attribute value doesn't end the tag.
+pub fn tokenize_html(html: &str) -> Vec<HtmlToken> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut inside_tag = false;
+    let mut quote: Option<char> = None;
+    for (i, c) in html.char_indices() {
+        match (inside_tag, quote, c) {
+            (false, _, '<') => {
+                if i > start {
+                    tokens.push(HtmlToken::Text(start..i));
+                }
+                start = i;
+                inside_tag = true;
+            },
+            (true, None, '"') | (true, None, '\'') => quote = Some(c),
+            (true, Some(q), _) if q == c => quote = None,
+            (true, None, '>') => {
+                tokens.push(HtmlToken::Tag(start..i+1));
+                start = i+1;
+                inside_tag = false;
+            },
+            _ => ()
+        }
+    }
+    // an unterminated tag is kept as text
+    if start < html.len() {
+        tokens.push(HtmlToken::Text(start..html.len()));
+    }
+    tokens
+}
+
+/// the text of a heading, without formatting,
+/// as used in a table of contents or to create its anchor.
+/// Whitespace is normalized, so `# **a**   b` gives `a b`.
+pub fn heading_plain_text(events: &[(Event, Range<usize>)]) -> String {
+    plain_text(events)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// an event transform that adds `prefix` before the relative urls of the images,
+/// like `https://cdn.example.com/` to load them from a cdn.
+/// See [`crate::MarkdownProps::event_transforms`]
+pub fn prefix_image_urls(prefix: &str) -> impl Fn(&mut Vec<(Event, Range<usize>)>) + '_ {
+    move |events: &mut Vec<(Event, Range<usize>)>| {
+        for (e, _) in events.iter_mut() {
+            if let Event::Start(Tag::Image{dest_url, ..}) = e {
+                let scheme = dest_url.split('/').next().unwrap_or_default().contains(':');
+                if !scheme && !dest_url.starts_with('/') && !dest_url.starts_with('#') {
+                    *dest_url = format!("{prefix}{dest_url}").into();
+                }
+            }
+        }
+    }
+}
+
+/// the default anchor id of a heading with the text `text`:
+/// `My Section!` gives `my-section`
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() || c == '_' {
+            slug.extend(c.to_lowercase());
+        }
+        else if (c.is_whitespace() || c == '-') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug
+}
+
+/// the index of the end of the heading that starts at `start`
+pub fn heading_end(stream: &[(Event, Range<usize>)], start: usize, level: HeadingLevel) -> usize {
+    stream[start..].iter()
+        .position(|(e, _)| e == &Event::End(TagEnd::Heading(level)))
+        .map(|n| start + n)
+        .unwrap_or(stream.len())
+}
+
+/// the explicit ids of the headings of `source`, like `b` in `# a {#b}`.
+/// The source is parsed a second time, only if it can contain one
+pub fn explicit_heading_ids(source: &str, options: Options) -> HashSet<String> {
+    if !options.contains(Options::ENABLE_HEADING_ATTRIBUTES) || !source.contains("{#") {
+        return HashSet::new()
+    }
+    ParserOffsetIter::new_ext(source, options, false)
+        .filter_map(|(e, _)| match e {
+            Event::Start(Tag::Heading{id: Some(id), ..}) => Some(id.to_string()),
+            _ => None
+        })
+        .collect()
+}
+
+/// gives an id to every heading of a stream of events,
+/// with `slugify` of the text of the heading.
+/// Duplicate ids get a `-1`, `-2`... suffix, in the order of the document.
+/// Headings with an explicit id, like `# a {#b}`, keep it:
+/// the explicit ids of the whole document, see [`explicit_heading_ids`],
+/// are never given to another heading
+pub struct HeadingIds<'a, I> {
+    stream: I,
+    slugify: &'a dyn Fn(&str) -> String,
+    /// the ids given so far
+    used: HashSet<String>,
+    /// the last suffix used for each slug
+    suffixes: HashMap<String, usize>,
+    /// the events inside the last heading, that are not given yet
+    buffer: VecDeque<(Event<'a>, Range<usize>)>,
+}
+
+impl<'a, I> HeadingIds<'a, I> {
+    /// `explicit_ids` are the explicit ids of the document
+    pub fn new(stream: I, slugify: &'a dyn Fn(&str) -> String, explicit_ids: HashSet<String>) -> Self {
+        Self {
+            stream,
+            slugify,
+            used: explicit_ids,
+            suffixes: HashMap::new(),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// the id of a heading with the text `text`, that was not used before
+    fn unique_slug(&mut self, text: &str) -> Option<String> {
+        let base = (self.slugify)(text);
+        if base.is_empty() {
+            return None
+        }
+        let mut slug = base.clone();
+        while self.used.contains(&slug) {
+            let suffix = self.suffixes.entry(base.clone()).or_insert(0);
+            *suffix += 1;
+            slug = format!("{base}-{suffix}");
+        }
+        self.used.insert(slug.clone());
+        Some(slug)
+    }
+}
+
+impl<'a, I> Iterator for HeadingIds<'a, I>
+where I: Iterator<Item=(Event<'a>, Range<usize>)>
+{
+    type Item = (Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(x) = self.buffer.pop_front() {
+            return Some(x)
+        }
+        let (mut e, range) = self.stream.next()?;
+        let level = match &e {
+            Event::Start(Tag::Heading{id: None, level, ..}) => Some(*level),
+            Event::Start(Tag::Heading{id: Some(id), ..}) => {
+                self.used.insert(id.to_string());
+                None
+            },
+            _ => None
+        };
+        let level = match level {
+            Some(level) => level,
+            None => return Some((e, range))
+        };
+
+        let end = Event::End(TagEnd::Heading(level));
+        for x in self.stream.by_ref() {
+            let is_end = x.0 == end;
+            self.buffer.push_back(x);
+            if is_end {
+                break
+            }
+        }
+        let text = heading_plain_text(self.buffer.make_contiguous());
+        if let (Some(slug), Event::Start(Tag::Heading{id, ..})) = (self.unique_slug(&text), &mut e) {
+            *id = Some(slug.into());
+        }
+        Some((e, range))
+    }
+}
+
+/// the scheme of `url`, like `https` or `javascript`, in lowercase.
+/// It is `None` for relative urls
+pub fn url_scheme(url: &str) -> Option<String> {
+    // browsers ignore the whitespace inside the scheme, like in `java\tscript:`
+    let url: String = url.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let path_end = url.find(['/', '?', '#']).unwrap_or(url.len());
+    let (scheme, _) = url[..path_end].split_once(':')?;
+    Some(scheme.to_lowercase())
+}
+
+/// wether `url` is safe to render: relative urls and
+/// `http`, `https`, `mailto` and `tel` urls.
+/// `data:image/...` urls are only safe if `data_images` is true
+pub fn is_safe_url(url: &str, data_images: bool) -> bool {
+    match url_scheme(url).as_deref() {
+        None | Some("http" | "https" | "mailto" | "tel") => true,
+        Some("data") => data_images
+            && url.trim_start().to_lowercase().starts_with("data:image/"),
+        Some(_) => false,
+    }
+}
+
+/// decodes the html entities of `text`: numeric entities like `&#169;`
+/// and a small set of named entities, like `&nbsp;` or `&shy;`.
+/// Unknown entities are kept as they are.
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end+1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// the character corresponding to the entity `&name;`
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(|c: char| c == 'x' || c == 'X') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code)
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "ensp" => '\u{2002}',
+        "emsp" => '\u{2003}',
+        "thinsp" => '\u{2009}',
+        "zwnj" => '\u{200c}',
+        "zwj" => '\u{200d}',
+        "ndash" => '\u{2013}',
+        "mdash" => '\u{2014}',
+        "hellip" => '\u{2026}',
+        _ => return None
+    })
+}
+
+/// replaces the tabs at the start of each line of `code` with spaces,
+/// up to the next multiple of `width`.
+/// The other tabs are kept, so that string literals stay the same
+pub fn expand_leading_tabs(code: &str, width: usize) -> String {
+    let mut result = String::with_capacity(code.len());
+    for line in code.split_inclusive('\n') {
+        let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let mut column = 0;
+        for c in line[..indent_len].chars() {
+            if c == '\t' && width > 0 {
+                let spaces = width - column % width;
+                result.extend(std::iter::repeat(' ').take(spaces));
+                column += spaces;
+            } else {
+                result.push(c);
+                column += 1;
+            }
+        }
+        result.push_str(&line[indent_len..]);
+    }
+    result
+}
+
+/// splits `events` into the events of each top-level block,
+/// like a paragraph or a list
+pub fn top_level_blocks<'a>(events: impl Iterator<Item=(Event<'a>, Range<usize>)>)
+    -> Vec<Vec<(Event<'a>, Range<usize>)>> {
+    let mut blocks = Vec::new();
+    let mut block = Vec::new();
+    let mut depth = 0;
+    for (e, range) in events {
+        match &e {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+        block.push((e, range));
+        if depth == 0 {
+            blocks.push(std::mem::take(&mut block));
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// merges the adjacent texts of a stream of events into a single text event.
+/// Soft breaks are merged as newlines
+pub struct MergedTexts<I: Iterator> {
+    stream: Peekable<I>,
+}
+
+impl<I: Iterator> MergedTexts<I> {
+    pub fn new(stream: I) -> Self {
+        Self {
+            stream: stream.peekable()
+        }
+    }
+}
+
+impl<'a, I> Iterator for MergedTexts<I>
+where I: Iterator<Item=(Event<'a>, Range<usize>)>
+{
+    type Item = (Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, mut range) = self.stream.next()?;
+        let first = match e {
+            Event::Text(s) => s,
+            Event::SoftBreak => "\n".into(),
+            e => return Some((e, range))
+        };
+        let mut text: Option<String> = None;
+        while let Some((Event::Text(_) | Event::SoftBreak, _)) = self.stream.peek() {
+            let (next, next_range) = self.stream.next()?;
+            let text = text.get_or_insert_with(|| String::from(&*first));
+            match next {
+                Event::Text(s) => text.push_str(&s),
+                _ => text.push('\n'),
+            }
+            range.end = next_range.end;
+        }
+        match text {
+            Some(text) => Some((Event::Text(text.into()), range)),
+            None => Some((Event::Text(first), range)),
+        }
+    }
+}
+
+/// the url or email address inside `word`, like `https://a.b` in `(https://a.b).`,
+/// with its type
+fn bare_link(word: &str) -> Option<(&str, LinkType)> {
+    let word = word.trim_start_matches(['(', '[', '"', '\'']);
+    let mut link = word.trim_end_matches(['.', ',', ':', ';', '!', '?', '"', '\'', ']']);
+    // keep the parentheses of urls like `https://a.b/c_(d)`
+    while link.ends_with(')') && link.matches(')').count() > link.matches('(').count() {
+        link = link[..link.len()-1].trim_end_matches(['.', ',', ':', ';', '!', '?']);
+    }
+    let is_url = ["https://", "http://", "www."].iter()
+        .any(|p| link.len() > p.len() && link.to_lowercase().starts_with(p));
+    if is_url {
+        return Some((link, LinkType::Autolink))
+    }
+    let (local, domain) = link.split_once('@')?;
+    let is_email = !local.is_empty()
+        && local.chars().all(|c| c.is_ascii_alphanumeric() || "._%+-".contains(c))
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    is_email.then_some((link, LinkType::Email))
+}
+
+/// splits `text`, that starts at `start` in the source, into texts and links,
+/// for the urls and email addresses it contains.
+/// Returns None if there is no link
+fn linkify_text<'a>(text: &str, start: usize) -> Option<Vec<(Event<'a>, Range<usize>)>> {
+    let mut events = Vec::new();
+    // the end of the last link
+    let mut last = 0;
+    let words = text.split(char::is_whitespace)
+        .map(|w| (w.as_ptr() as usize - text.as_ptr() as usize, w));
+    for (offset, word) in words {
+        let (link, link_type) = match bare_link(word) {
+            Some(x) => x,
+            None => continue,
+        };
+        let link_start = offset + (link.as_ptr() as usize - word.as_ptr() as usize);
+        let link_end = link_start + link.len();
+        if link_start > last {
+            events.push((Event::Text(text[last..link_start].to_string().into()), start+last..start+link_start));
+        }
+        let dest_url: CowStr = match link.to_lowercase().starts_with("www.") {
+            true => format!("http://{link}").into(),
+            false => link.to_string().into(),
+        };
+        let range = start+link_start..start+link_end;
+        events.push((Event::Start(Tag::Link{link_type, dest_url, title: "".into(), id: "".into()}), range.clone()));
+        events.push((Event::Text(link.to_string().into()), range.clone()));
+        events.push((Event::End(TagEnd::Link), range));
+        last = link_end;
+    }
+    if events.is_empty() {
+        return None
+    }
+    if last < text.len() {
+        events.push((Event::Text(text[last..].to_string().into()), start+last..start+text.len()));
+    }
+    Some(events)
+}
+
+/// turns the urls and email addresses of the texts into links,
+/// like `https://a.b`, `www.a.b` or `a@b.c`.
+/// The texts inside links, images, code blocks and html are kept.
+/// Adjacent texts are joined to find the links, but they are only split
+/// if they are exactly their source, so that the ranges stay correct.
+pub struct Linkify<'a, I: Iterator<Item=(Event<'a>, Range<usize>)>> {
+    stream: Peekable<I>,
+    buffer: VecDeque<(Event<'a>, Range<usize>)>,
+    /// the number of links, images and code blocks around the current event
+    depth: usize,
+}
+
+impl<'a, I: Iterator<Item=(Event<'a>, Range<usize>)>> Linkify<'a, I> {
+    pub fn new(stream: I) -> Self {
+        Self {
+            stream: stream.peekable(),
+            buffer: VecDeque::new(),
+            depth: 0,
+        }
+    }
+}
+
+impl<'a, I> Iterator for Linkify<'a, I>
+where I: Iterator<Item=(Event<'a>, Range<usize>)>
+{
+    type Item = (Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(x) = self.buffer.pop_front() {
+            return Some(x)
+        }
+        let (e, range) = self.stream.next()?;
+        match &e {
+            Event::Start(Tag::Link{..} | Tag::Image{..} | Tag::CodeBlock(_)
+                         | Tag::HtmlBlock | Tag::MetadataBlock(_)) => self.depth += 1,
+            Event::End(TagEnd::Link | TagEnd::Image | TagEnd::CodeBlock
+                       | TagEnd::HtmlBlock | TagEnd::MetadataBlock(_)) => self.depth -= 1,
+            _ => ()
+        }
+        if self.depth > 0 || !matches!(e, Event::Text(_)) {
+            return Some((e, range))
+        }
+
+        let start = range.start;
+        let mut texts = vec![(e, range)];
+        while let Some((Event::Text(_), _)) = self.stream.peek() {
+            texts.push(self.stream.next()?);
+        }
+        let mut joined = String::new();
+        let mut exact = true;
+        for (e, range) in &texts {
+            if let Event::Text(s) = e {
+                exact &= range.start == start + joined.len() && range.len() == s.len();
+                joined.push_str(s);
+            }
+        }
+        match linkify_text(&joined, start).filter(|_| exact) {
+            Some(events) => self.buffer.extend(events),
+            None => self.buffer.extend(texts),
+        }
+        self.buffer.pop_front()
+    }
+}
+
+/// the lines to highlight in a code block, given at the end of its info string,
+/// like `{1,4-6}` in `rust {1,4-6}`. Lines are counted from 1
+pub fn highlighted_lines(info: &str) -> Vec<RangeInclusive<usize>> {
+    let info = info.trim_end();
+    let spec = match (info.rfind('{'), info.strip_suffix('}')) {
+        (Some(start), Some(info)) => &info[start+1..],
+        _ => return Vec::new()
+    };
+    spec.split(',')
+        .filter_map(|part| match part.split_once('-') {
+            Some((a, b)) => Some(a.trim().parse().ok()?..=b.trim().parse().ok()?),
+            None => {
+                let n = part.trim().parse().ok()?;
+                Some(n..=n)
+            }
+        })
+        .collect()
+}
+
+/// splits inline code that starts with its language, like `rust:let x = 1`,
+/// into the language and the code.
+/// The language is a single word, like `rust`, `c++` or `c#`
+pub fn inline_code_language(code: &str) -> Option<(&str, &str)> {
+    let (lang, content) = code.split_once(':')?;
+    let valid = !lang.is_empty()
+        && lang.chars().all(|c| c.is_alphanumeric() || "+#-_".contains(c))
+        && !content.is_empty();
+    valid.then_some((lang, content))
+}
+
+/// the title of a code block, like a file name,
+/// given by `title="main.rs"` or `filename=main.rs` in its info string
+pub fn code_block_title(info: &str) -> Option<String> {
+    let (i, key) = info.match_indices("title=")
+        .chain(info.match_indices("filename="))
+        .find(|(i, _)| *i == 0 || info[..*i].ends_with(char::is_whitespace))?;
+    let value = &info[i+key.len()..];
+    let title = match value.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => value.split(char::is_whitespace).next()?,
+    };
+    Some(title.to_string()).filter(|t| !t.is_empty())
+}
+
+/// escapes `text`, so that it is shown as is inside html
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// removes every html tag from `raw_html`, keeping only the text.
+/// The entities of the text are decoded.
+pub fn strip_html_tags(raw_html: &str) -> String {
+    let mut text = String::new();
+    let mut inside_tag = false;
+    for c in raw_html.chars() {
+        match c {
+            '<' => inside_tag = true,
+            '>' if inside_tag => inside_tag = false,
+            _ if !inside_tag => text.push(c),
+            _ => ()
+        }
+    }
+    decode_entities(&text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pulldown_cmark_wikilink::{ParserOffsetIter, Options};
+
+    fn with_hard_breaks(source: &str) -> Vec<Event> {
+        soft_to_hard_breaks(ParserOffsetIter::new_ext(source, Options::all(), false))
+            .map(|(e, _)| e)
+            .collect()
+    }
+
+    #[test]
+    fn hard_break_in_paragraph(){
+        let events = with_hard_breaks("a\nb");
+        assert!(events.contains(&Event::HardBreak));
+        assert!(!events.contains(&Event::SoftBreak));
+    }
+
+    #[test]
+    fn hard_break_in_list_item(){
+        let events = with_hard_breaks("- a\n  b");
+        assert!(events.contains(&Event::HardBreak));
+    }
+
+    #[test]
+    fn no_hard_break_in_heading(){
+        let events = with_hard_breaks("a\nb\n===");
+        assert!(events.contains(&Event::SoftBreak));
+        assert!(!events.contains(&Event::HardBreak));
+    }
+
+    fn text_inside(source: &str, end: TagEnd) -> String {
+        let mut stream = ParserOffsetIter::new_ext(source, Options::all(), false);
+        // skip the opening tag
+        stream.next();
+        collect_text(&mut stream, end)
+    }
+
+    #[test]
+    fn text_of_empty_code_block(){
+        assert_eq!(text_inside("```\n```", TagEnd::CodeBlock), "");
+    }
+
+    #[test]
+    fn text_of_multiline_code_block(){
+        assert_eq!(text_inside("```rust\na\nb\n```", TagEnd::CodeBlock), "a\nb\n");
+    }
+
+    #[test]
+    fn text_of_unterminated_code_block(){
+        assert_eq!(text_inside("```rust\nfn main() {\n", TagEnd::CodeBlock), "fn main() {\n");
+        assert_eq!(text_inside("```", TagEnd::CodeBlock), "");
+    }
+
+    #[test]
+    fn unexpected_text_content(){
+        let mut stream = ParserOffsetIter::new_ext("# a *b* c\n\nd", Options::all(), false);
+        stream.next();
+        assert_eq!(collect_text(&mut stream, TagEnd::Heading(HeadingLevel::H1)), "a ");
+        assert_eq!(stream.next().map(|x| x.0), Some(Event::Start(Tag::Paragraph)));
+    }
+
+    #[test]
+    fn text_with_breaks(){
+        assert_eq!(text_inside("a\nb  \nc", TagEnd::Paragraph), "a\nb\nc");
+    }
+
+    #[test]
+    fn plain_text_of_link(){
+        let mut stream = ParserOffsetIter::new_ext("[**bold** `code`](x)", Options::all(), false);
+        // skip the paragraph and link tags
+        stream.next();
+        stream.next();
+        let events = collect_events(&mut stream, TagEnd::Link);
+        assert_eq!(plain_text(&events), "bold code");
+        assert_eq!(stream.next().map(|(e, _)| e), Some(Event::End(TagEnd::Paragraph)));
+    }
+
+    #[test]
+    fn inline_html_only(){
+        let events = [
+            (Event::InlineHtml("<X/>".into()), 0..4),
+            (Event::SoftBreak, 4..5),
+            (Event::InlineHtml("<Y/>".into()), 5..9),
+        ];
+        assert_eq!(only_inline_html(&events), Some("<X/> <Y/>".to_string()));
+
+        let events = [
+            (Event::Text("z".into()), 0..1),
+            (Event::InlineHtml("<X/>".into()), 1..5),
+        ];
+        assert_eq!(only_inline_html(&events), None);
+    }
+
+    #[test]
+    fn countdown_list(){
+        let source = "3. a\n2. b\n1. c";
+        let mut stream = ParserOffsetIter::new_ext(source, Options::all(), false);
+        // skip the list tag
+        stream.next();
+        let events = collect_events(&mut stream, TagEnd::List(true));
+        assert!(is_reversed_list(source, &events));
+
+        let source = "3. a\n4. b";
+        let mut stream = ParserOffsetIter::new_ext(source, Options::all(), false);
+        stream.next();
+        let events = collect_events(&mut stream, TagEnd::List(true));
+        assert!(!is_reversed_list(source, &events));
+    }
+
+    #[test]
+    fn table_body(){
+        let mut stream = ParserOffsetIter::new_ext("|a|b|\n|-|-|\n|1|2|\n|3|4|", Options::all(), false);
+        // skip the table tag
+        stream.next();
+        let mut head = collect_events(&mut stream, TagEnd::Table);
+        let body = split_table_head(&mut head);
+        assert_eq!(head.first().map(|(e, _)| e), Some(&Event::Start(Tag::TableHead)));
+        assert_eq!(head.last().map(|(e, _)| e), Some(&Event::End(TagEnd::TableHead)));
+        let rows = body.iter()
+            .filter(|(e, _)| *e == Event::Start(Tag::TableRow))
+            .count();
+        assert_eq!(rows, 2);
+    }
+
+    #[test]
+    fn tokenize(){
+        use HtmlToken::*;
+        let html = "<div title=\"a > b\">**hi**</div>";
+        assert_eq!(tokenize_html(html), vec![Tag(0..19), Text(19..25), Tag(25..31)]);
+        assert_eq!(&html[19..25], "**hi**");
+    }
+
+    #[test]
+    fn heading_text(){
+        let events: Vec<_> = ParserOffsetIter::new_ext("#  **bold**  and `code` ", Options::all(), false)
+            .collect();
+        assert_eq!(heading_plain_text(&events), "bold and code");
+    }
+
+    #[test]
+    fn typographic_entities(){
+        assert_eq!(decode_entities("a&nbsp;b&shy;c"), "a\u{a0}b\u{ad}c");
+        assert_eq!(decode_entities("&#169; &#x41;"), "\u{a9} A");
+        assert_eq!(decode_entities("R&D &unknown; &"), "R&D &unknown; &");
+    }
+
+    #[test]
+    fn entities_in_markdown_text(){
+        // the parser already decodes the entities of the markdown text
+        let text: String = ParserOffsetIter::new_ext("a&nbsp;b&shy;c", Options::all(), false)
+            .filter_map(|(e, _)| match e {
+                Event::Text(s) => Some(s.to_string()),
+                _ => None
+            })
+            .collect();
+        assert_eq!(text, "a\u{a0}b\u{ad}c");
+    }
+
+    fn code_spans(source: &str) -> Vec<String> {
+        ParserOffsetIter::new_ext(source, Options::all(), false)
+            .filter_map(|(e, _)| match e {
+                Event::Code(s) => Some(s.to_string()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn html_in_code_span(){
+        // the content of code spans is literal: it is neither html nor decoded
+        assert_eq!(code_spans("`<script>alert(1)</script>`"), vec!["<script>alert(1)</script>"]);
+        assert_eq!(code_spans("`&lt;div&gt; &amp;`"), vec!["&lt;div&gt; &amp;"]);
+    }
+
+    #[test]
+    fn strip_tags(){
+        assert_eq!(strip_html_tags("<div class=\"a\">hello <b>world</b></div>"), "hello world");
+        assert_eq!(strip_html_tags("no tags"), "no tags");
+        assert_eq!(strip_html_tags("<p>a&nbsp;b</p>"), "a\u{a0}b");
+    }
+
+    #[test]
+    fn merged_paragraph(){
+        let stream = ParserOffsetIter::new_ext("a\nb &amp; c *d*", Options::all(), false);
+        let events: Vec<_> = MergedTexts::new(stream).map(|(e, _)| e).collect();
+        assert_eq!(events[1], Event::Text("a\nb & c ".into()));
+        assert_eq!(events[3], Event::Text("d".into()));
+    }
+
+    #[test]
+    fn leading_tabs(){
+        let code = "fn f() {\n\tlet s = \"a\tb\";\n \t\tx\n}\n";
+        assert_eq!(
+            expand_leading_tabs(code, 4),
+            "fn f() {\n    let s = \"a\tb\";\n        x\n}\n"
+        );
+        assert_eq!(expand_leading_tabs("\tx\ty", 2), "  x\ty");
+    }
+
+    #[test]
+    fn closing_tags_match(){
+        let source = "H~2~O and x^2^\n\n> - *a* **b** ~~c~~ [d](e)\n\n| f |\n|---|\n| g |";
+        let mut open = Vec::new();
+        for (e, _) in ParserOffsetIter::new_ext(source, Options::all(), false) {
+            match e {
+                Event::Start(t) => open.push(as_closing_tag(&t)),
+                Event::End(t) => assert_eq!(open.pop(), Some(t)),
+                _ => ()
+            }
+        }
+        assert!(open.is_empty());
+    }
+
+    fn heading_ids(source: &str) -> Vec<String> {
+        let explicit_ids = explicit_heading_ids(source, Options::all());
+        HeadingIds::new(ParserOffsetIter::new_ext(source, Options::all(), false), &slugify, explicit_ids)
+            .filter_map(|(e, _)| match e {
+                Event::Start(Tag::Heading{id, ..}) => Some(id?.to_string()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slugs(){
+        assert_eq!(slugify("My *Section*!"), "my-section");
+        assert_eq!(slugify(" a  -  b "), "a-b");
+    }
+
+    #[test]
+    fn duplicate_heading_ids(){
+        assert_eq!(
+            heading_ids("# Intro\n## Intro\n# Other {#intro-1}\n# Intro"),
+            vec!["intro", "intro-2", "intro-1", "intro-3"]
+        );
+    }
+
+    #[test]
+    fn escape(){
+        assert_eq!(escape_html("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn line_highlights(){
+        let lines = highlighted_lines("rust {2-3}");
+        let highlighted: Vec<_> = (1..=5).filter(|n| lines.iter().any(|r| r.contains(n))).collect();
+        assert_eq!(highlighted, vec![2, 3]);
+        assert_eq!(highlighted_lines("rust {1, 4-6}"), vec![1..=1, 4..=6]);
+        assert!(highlighted_lines("rust").is_empty());
+    }
+
+    #[test]
+    fn inline_code_languages(){
+        assert_eq!(inline_code_language("rust:let x = 1"), Some(("rust", "let x = 1")));
+        assert_eq!(inline_code_language("c++:a::b"), Some(("c++", "a::b")));
+        assert_eq!(inline_code_language("let x = a:b"), None);
+        assert_eq!(inline_code_language(":a"), None);
+        assert_eq!(inline_code_language("rust:"), None);
+        assert_eq!(inline_code_language("x"), None);
+    }
+
+    #[test]
+    fn code_titles(){
+        assert_eq!(code_block_title("rust title=\"src/main.rs\""), Some("src/main.rs".to_string()));
+        assert_eq!(code_block_title("rust filename=a.rs {1-2}"), Some("a.rs".to_string()));
+        assert_eq!(code_block_title("title=\"my file\""), Some("my file".to_string()));
+        assert_eq!(code_block_title("rust subtitle=a"), None);
+        assert_eq!(code_block_title("rust"), None);
+    }
+
+    fn admonition(source: &str) -> (Option<&'static str>, String) {
+        let mut events: Vec<_> = ParserOffsetIter::new_ext(source, Options::all(), false)
+            .collect();
+        // the events inside the block quote
+        events.remove(0);
+        events.pop();
+        let kind = strip_admonition(&mut events);
+        (kind, plain_text(&events))
+    }
+
+    #[test]
+    fn heading_offset(){
+        assert_eq!(offset_heading_level(HeadingLevel::H2, 0), 2);
+        assert_eq!(offset_heading_level(HeadingLevel::H2, 2), 4);
+        assert_eq!(offset_heading_level(HeadingLevel::H5, 1), 6);
+        assert_eq!(offset_heading_level(HeadingLevel::H6, 1), 6);
+        assert_eq!(offset_heading_level(HeadingLevel::H1, 255), 6);
+    }
+
+    fn linkified(source: &str) -> Vec<(Event, &str)> {
+        let stream = ParserOffsetIter::new_ext(source, Options::all(), false);
+        Linkify::new(stream)
+            .map(|(e, range)| (e, &source[range]))
+            .collect()
+    }
+
+    #[test]
+    fn bare_urls(){
+        let source = "see https://example.com/a_b, or www.rust-lang.org.";
+        let events = linkified(source);
+        assert!(events.contains(&(Event::Start(Tag::Link{
+            link_type: LinkType::Autolink,
+            dest_url: "https://example.com/a_b".into(),
+            title: "".into(),
+            id: "".into()
+        }), "https://example.com/a_b")));
+        assert!(events.contains(&(Event::Start(Tag::Link{
+            link_type: LinkType::Autolink,
+            dest_url: "http://www.rust-lang.org".into(),
+            title: "".into(),
+            id: "".into()
+        }), "www.rust-lang.org")));
+        assert!(events.contains(&(Event::Text(", or ".into()), ", or ")));
+        assert!(events.contains(&(Event::Text(".".into()), ".")));
+    }
+
+    #[test]
+    fn bare_emails(){
+        let events = linkified("mail me at a.b@c.org!");
+        assert!(events.contains(&(Event::Start(Tag::Link{
+            link_type: LinkType::Email,
+            dest_url: "a.b@c.org".into(),
+            title: "".into(),
+            id: "".into()
+        }), "a.b@c.org")));
+        assert!(events.contains(&(Event::Text("a.b@c.org".into()), "a.b@c.org")));
+    }
+
+    #[test]
+    fn no_links_inside_links_and_code(){
+        let events = linkified("[https://a.b](https://c.d) `www.x.y`\n\n```\nwww.x.y\n```");
+        let links = events.iter().filter(|(e, _)| matches!(e, Event::Start(Tag::Link{..}))).count();
+        assert_eq!(links, 1);
+    }
+
+    #[test]
+    fn blocks(){
+        let stream = ParserOffsetIter::new_ext("# a\n\nb *c*\n\n---\n- d\n- e", Options::all(), false);
+        let blocks = top_level_blocks(stream);
+        let sizes: Vec<_> = blocks.iter().map(|b| b.len()).collect();
+        // heading, paragraph, rule and list
+        assert_eq!(sizes, vec![3, 6, 1, 8]);
+    }
+
+    #[test]
+    fn image_sizes(){
+        assert_eq!(image_size("=200x100", "a.png"), (Some(200), Some(100)));
+        assert_eq!(image_size("a cat =200x", "a.png"), (Some(200), None));
+        assert_eq!(image_size("=x100", "a.png?width=3"), (None, Some(100)));
+        assert_eq!(image_size("", "a.png?width=300&height=20#x"), (Some(300), Some(20)));
+        assert_eq!(image_size("a cat", "a.png"), (None, None));
+        assert_eq!(image_size("=big", "a.png?w=1"), (None, None));
+    }
+
+    #[test]
+    fn image_captions(){
+        assert_eq!(image_caption("My caption"), "My caption");
+        assert_eq!(image_caption("My caption =200x100"), "My caption");
+        assert_eq!(image_caption("=200x"), "");
+        assert_eq!(image_caption(" "), "");
+    }
+
+    #[test]
+    fn captioned_images(){
+        let paragraph = |source| {
+            let events: Vec<_> = ParserOffsetIter::new_ext(source, Options::empty(), false).collect();
+            events[1..events.len()-1].to_vec()
+        };
+        assert!(is_captioned_image(&paragraph("![a](a.png \"A\")")));
+        assert!(!is_captioned_image(&paragraph("![a](a.png)")));
+        assert!(!is_captioned_image(&paragraph("![a](a.png \"A\") b")));
+        assert!(!is_captioned_image(&paragraph("![a](a.png \"A\")![b](b.png \"B\")")));
+    }
+
+    #[test]
+    fn toml_frontmatter(){
+        let source = "+++\ntitle = \"a\"\n+++\n# b";
+        assert_eq!(extra_frontmatter(source), Some((FrontmatterFormat::Toml, "title = \"a\"\n", 20)));
+        assert_eq!(&source[20..], "# b");
+        assert_eq!(extra_frontmatter("+++\ntitle = \"a\"\n"), None);
+    }
+
+    #[test]
+    fn json_frontmatter(){
+        assert_eq!(extra_frontmatter("--- json\n{\"a\": 1}\n---\nb"),
+                   Some((FrontmatterFormat::Json, "{\"a\": 1}\n", 22)));
+        assert_eq!(extra_frontmatter("{\n\"a\": 1\n}\nb"), None);
+    }
+
+    #[test]
+    fn yaml_is_not_extra_frontmatter(){
+        assert_eq!(extra_frontmatter("---\na: 1\n---\n"), None);
+        assert_eq!(extra_frontmatter("a\n+++\nb\n+++\n"), None);
+    }
+
+    #[test]
+    fn admonitions(){
+        assert_eq!(admonition("> [!NOTE]\n> be careful"), (Some("note"), "be careful".to_string()));
+        assert_eq!(admonition("> [!warning] hot"), (Some("warning"), "hot".to_string()));
+        assert_eq!(admonition("> [!TIP]\n>\n> a"), (Some("tip"), "a".to_string()));
+        assert_eq!(admonition("> [!OTHER]\n> a").0, None);
+        assert_eq!(admonition("> a").0, None);
+    }
+
+    #[test]
+    fn wikilinks(){
+        assert_eq!(wikilink_page("[[Some Page]]").as_deref(), Some("Some Page"));
+        assert_eq!(wikilink_page("[[Installation|how to install]]").as_deref(), Some("Installation"));
+        assert_eq!(wikilink_page("[text](url)"), None);
+    }
+
+    /// the text inside the first component `Card` of `source`,
+    /// and the text after it
+    fn card_content(source: &str) -> (String, String) {
+        let mut stream = ParserOffsetIter::new_ext(source, Options::all(), false);
+        // the html block of the start of the component
+        stream.by_ref().take(3).for_each(drop);
+        let (events, end) = component_events(&mut stream, "Card");
+        assert_eq!(end, ComponentEnd::Closed);
+        let rest: Vec<_> = stream.collect();
+        (plain_text(&events), plain_text(&rest))
+    }
+
+    #[test]
+    fn component_content(){
+        assert_eq!(card_content("<Card>\n\na\n\n</Card>\n\nb"), ("a".to_string(), "b".to_string()));
+    }
+
+    #[test]
+    fn unclosed_component(){
+        let mut stream = ParserOffsetIter::new_ext("<Card>\n\na\n\n<Card/>\n", Options::all(), false);
+        stream.by_ref().take(3).for_each(drop);
+        let (events, end) = component_events(&mut stream, "Card");
+        assert_eq!((plain_text(&events).as_str(), end), ("a", ComponentEnd::Unclosed));
+
+        let mut stream = ParserOffsetIter::new_ext("> <Card>\n>\n> a\n\nb", Options::all(), false);
+        stream.by_ref().take(4).for_each(drop);
+        let (_, end) = component_events(&mut stream, "Card");
+        assert_eq!(end, ComponentEnd::ParentEnded);
+    }
+
+    #[test]
+    fn nested_components(){
+        let (inside, after) = card_content("<Card>\n\na\n\n<Card>\n\nb\n\n</Card>\n\nc\n\n</Card>\n\nd");
+        assert_eq!((inside.contains('b'), inside.contains('c'), after.as_str()), (true, true, "d"));
+
+        let (inside, after) = card_content(
+            "<Card>\n\n<Card>\n\n<Card>\n\na\n\n</Card>\n\n</Card>\n\nb\n\n</Card>\n\nc"
+        );
+        assert_eq!((inside.contains('a'), inside.contains('b'), after.as_str()), (true, true, "c"));
+    }
+}